@@ -0,0 +1,87 @@
+/* Third-Party crates */
+use printpdf::*;
+
+/* Local */
+use crate::decorations::{add_line, add_rect, black};
+use crate::format::NumberFormat;
+use crate::text::{aligned_x, Alignment};
+use crate::utils::PageSize;
+
+const MM_TO_PT: f64 = 72.0 / 25.4;
+const MAX_FONT_SIZE: f64 = 10.0;
+const GRID_FONT_SIZE: f64 = 7.0;
+
+// Draws a horizontal bar chart in the box whose bottom-left corner is (x, y). Labels take the
+// left 30% of the box, bars are scaled to the largest value and start from a common baseline.
+#[allow(clippy::too_many_arguments)]
+pub fn add_bar_chart(layer: &PdfLayerReference, page_size: &PageSize, labels: &[String], values: &[f64], x: f64, y: f64, width: f64, height: f64, color: Color, font: &IndirectFontRef) {
+    self::add_bar_chart_with_grid(layer, page_size, labels, values, x, y, width, height, color, font, 0);
+}
+
+// Like add_bar_chart with about grid_steps faint gridlines across the bars at round values,
+// labelled beneath the chart. The scale is extended to the gridline after the largest value.
+#[allow(clippy::too_many_arguments)]
+pub fn add_bar_chart_with_grid(layer: &PdfLayerReference, page_size: &PageSize, labels: &[String], values: &[f64], x: f64, y: f64, width: f64, height: f64, color: Color, font: &IndirectFontRef, grid_steps: usize) {
+    let count = labels.len().min(values.len());
+    if count == 0 {
+        return;
+    }
+    let width = width.min(page_size.width - page_size.margin_width - x);
+    let label_width = width * 0.3;
+    let chart_x = x + label_width;
+    let chart_width = width - label_width;
+    let slot = height / count as f64;
+    let bar_height = slot * 0.7;
+    let font_size = (bar_height * MM_TO_PT).min(MAX_FONT_SIZE);
+    let mut max = values.iter().take(count).cloned().fold(0.0, f64::max);
+
+    if grid_steps > 0 && max > 0.0 {
+        let step = self::nice_step(max / grid_steps as f64);
+        max = (max / step).ceil() * step;
+        let mut format = NumberFormat::default();
+        format.set_decimals((-step.log10().floor()).max(0.0) as usize);
+
+        layer.set_outline_color(Color::Greyscale(Greyscale::new(0.8, None)));
+        layer.set_outline_thickness(0.5);
+        for index in 0..=(max / step).round() as usize {
+            let value = index as f64 * step;
+            let line_x = chart_x + value / max * chart_width;
+            if value > 0.0 {
+                add_line(layer, line_x, y, line_x, y + height);
+            }
+            let text = format.format(value);
+            let text_x = aligned_x(&text, line_x, Alignment::Center, font, GRID_FONT_SIZE);
+            layer.use_text(text, GRID_FONT_SIZE, Mm(text_x), Mm(y - GRID_FONT_SIZE / MM_TO_PT - 1.0), font);
+        }
+        layer.set_outline_color(black());
+        layer.set_outline_thickness(1.0);
+    }
+
+    for (index, (label, value)) in labels.iter().zip(values.iter()).take(count).enumerate() {
+        let slot_top = y + height - slot * index as f64;
+        let bar_y = slot_top - (slot + bar_height) / 2.0;
+        let length = match max > 0.0 {
+            true => value.max(0.0) / max * chart_width,
+            false => 0.0
+        };
+        if length > 0.0 {
+            add_rect(layer, chart_x, bar_y, length, bar_height, Some(color.clone()), None);
+        }
+        let label_x = aligned_x(label, chart_x - 1.0, Alignment::Right, font, font_size);
+        let label_y = bar_y + (bar_height - font_size / MM_TO_PT * 0.7) / 2.0;
+        layer.use_text(label.as_str(), font_size, Mm(label_x), Mm(label_y), font);
+    }
+    add_line(layer, chart_x, y, chart_x, y + height);
+}
+
+// Rounds a step up to 1, 2 or 5 times a power of ten
+fn nice_step(raw: f64) -> f64 {
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = match raw / magnitude {
+        f if f <= 1.0 => 1.0,
+        f if f <= 2.0 => 2.0,
+        f if f <= 5.0 => 5.0,
+        _ => 10.0
+    };
+    step * magnitude
+}
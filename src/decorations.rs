@@ -0,0 +1,123 @@
+/* Third-Party crates */
+use printpdf::*;
+
+/* Local */
+use crate::utils::{image_size_mm, PageSize, IMAGE_DPI};
+
+pub enum BgMode {
+    Center,
+    Stretch,
+    Tile
+}
+
+// printpdf does not let us register an ExtGState with an alpha value, so opacity is applied to
+// the pixel data instead by blending 8-bit RGB and greyscale images towards white (paper).
+// Other colour spaces and compressed images are left untouched.
+pub fn fade_image(image: &printpdf::Image, opacity: f64) -> printpdf::Image {
+    let mut faded = image.image.clone();
+    let opacity = opacity.clamp(0.0, 1.0);
+    let blendable = match (faded.color_space, faded.bits_per_component) {
+        (ColorSpace::Rgb, ColorBits::Bit8) | (ColorSpace::Greyscale, ColorBits::Bit8) => faded.image_filter.is_none(),
+        _ => false
+    };
+    if blendable {
+        for value in faded.image_data.iter_mut() {
+            *value = (255.0 - (255.0 - *value as f64) * opacity).round() as u8;
+        }
+    }
+    printpdf::Image::from(faded)
+}
+
+// Draws an image behind the page content. Call it before anything else is drawn on the layer,
+// PDF paints in order so later content sits on top.
+pub fn add_background_image(layer: &PdfLayerReference, page_size: &PageSize, image: printpdf::Image, opacity: f64, mode: BgMode) {
+    let image = self::fade_image(&image, opacity);
+    let (width, height) = image_size_mm(&image, IMAGE_DPI);
+    if width <= 0.0 || height <= 0.0 {
+        return;
+    }
+
+    match mode {
+        BgMode::Center => {
+            let x = (page_size.width - width) / 2.0;
+            let y = (page_size.height - height) / 2.0;
+            image.add_to_layer(layer.clone(), Some(Mm(x)), Some(Mm(y)), None, None, None, Some(IMAGE_DPI));
+        }
+        BgMode::Stretch => {
+            let scale_x = page_size.width / width;
+            let scale_y = page_size.height / height;
+            image.add_to_layer(layer.clone(), None, None, None, Some(scale_x), Some(scale_y), Some(IMAGE_DPI));
+        }
+        BgMode::Tile => {
+            let mut y = 0.0;
+            while y < page_size.height {
+                let mut x = 0.0;
+                while x < page_size.width {
+                    printpdf::Image::from(image.image.clone()).add_to_layer(layer.clone(), Some(Mm(x)), Some(Mm(y)), None, None, None, Some(IMAGE_DPI));
+                    x += width;
+                }
+                y += height;
+            }
+        }
+    }
+}
+
+// Draws a rectangle with its bottom-left corner at (x, y), filled and/or stroked
+pub fn add_rect(layer: &PdfLayerReference, x: f64, y: f64, width: f64, height: f64, fill: Option<Color>, stroke: Option<Color>) {
+    self::add_rect_with_shadow(layer, x, y, width, height, fill, stroke, None);
+}
+
+// Like add_rect with a filled copy behind it moved by (offset x, offset y) in mm, e.g. (1.0, -1.0)
+// for a shadow down and to the right. There's no transparency in printpdf, so pick a light colour
+// for a soft shadow.
+#[allow(clippy::too_many_arguments)]
+pub fn add_rect_with_shadow(layer: &PdfLayerReference, x: f64, y: f64, width: f64, height: f64, fill: Option<Color>, stroke: Option<Color>, shadow: Option<(f64, f64, Color)>) {
+    if let Some((offset_x, offset_y, color)) = shadow {
+        self::add_rect(layer, x + offset_x, y + offset_y, width, height, Some(color), None);
+    }
+    let has_fill = fill.is_some();
+    let has_stroke = stroke.is_some();
+    if let Some(color) = fill {
+        layer.set_fill_color(color);
+    }
+    if let Some(color) = stroke {
+        layer.set_outline_color(color);
+    }
+    layer.add_shape(Line {
+        points: vec![
+            (Point::new(Mm(x), Mm(y + height)), false),
+            (Point::new(Mm(x + width), Mm(y + height)), false),
+            (Point::new(Mm(x + width), Mm(y)), false),
+            (Point::new(Mm(x), Mm(y)), false),
+        ],
+        is_closed: true,
+        has_fill,
+        has_stroke,
+        is_clipping_path: false,
+    });
+    layer.set_fill_color(self::black());
+    layer.set_outline_color(self::black());
+}
+
+// Draws a straight line between two points
+pub fn add_line(layer: &PdfLayerReference, x1: f64, y1: f64, x2: f64, y2: f64) {
+    layer.add_shape(Line {
+        points: vec![(Point::new(Mm(x1), Mm(y1)), false), (Point::new(Mm(x2), Mm(y2)), false)],
+        is_closed: false,
+        has_fill: false,
+        has_stroke: true,
+        is_clipping_path: false,
+    });
+}
+
+pub fn black() -> Color {
+    Color::Greyscale(Greyscale::new(0.0, None))
+}
+
+// Draws a frame inset_mm in from each page edge, pass the page margin to frame the content area.
+// The thickness is in points, like printpdf's outline thickness.
+pub fn add_page_frame(layer: &PdfLayerReference, page_size: &PageSize, inset_mm: f64, thickness: f64, color: Color) {
+    layer.set_outline_thickness(thickness);
+    self::add_rect(layer, inset_mm, inset_mm, page_size.width - inset_mm * 2.0, page_size.height - inset_mm * 2.0, None, Some(color));
+    layer.set_outline_thickness(1.0);
+}
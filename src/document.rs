@@ -0,0 +1,190 @@
+/*Built-in*/
+use std::fmt;
+use std::io::{BufWriter, Write};
+
+/* Third-Party crates */
+use printpdf::*;
+
+/* Local */
+use crate::text::{line_height, sanitize_text, wrap_text, ControlCharPolicy};
+use crate::utils::{add_table, image_size_mm, table_page_count, PageSize, Table, IMAGE_DPI};
+
+const SECTION_SPACING: f64 = 5.0;
+
+#[derive(Debug)]
+pub enum RenderError {
+    EmptyTable,
+    ImageTooLarge
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::EmptyTable => write!(f, "A table section needs at least a header row"),
+            RenderError::ImageTooLarge => write!(f, "The image does not fit in the printable area of a page")
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+pub type Result<T> = std::result::Result<T, RenderError>;
+
+// State shared by sections while a document is rendered
+pub struct RenderContext<'a> {
+    pub doc: &'a PdfDocumentReference,
+    pub page_size: PageSize,
+    pub layer: PdfLayerReference,
+    pub y: f64,
+    pub regular: IndirectFontRef,
+    pub bold: IndirectFontRef,
+    pub page_count: usize
+}
+
+impl<'a> RenderContext<'a> {
+    pub fn top(&self) -> f64 {
+        self.page_size.printable_top()
+    }
+    pub fn new_page(&mut self) {
+        self.page_count += 1;
+        let (page, layer) = self.doc.add_page(Mm(self.page_size.width), Mm(self.page_size.height), format!("Page {}", self.page_count));
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = self.top();
+    }
+    // Starts a new page when less than height mm is left above the bottom margin
+    pub fn ensure_space(&mut self, height: f64) {
+        if self.y - height < self.page_size.margin_height {
+            self.new_page();
+        }
+    }
+}
+
+pub trait Section {
+    fn render(&self, ctx: &mut RenderContext) -> Result<()>;
+}
+
+pub struct TableSection {
+    pub table: Table
+}
+
+impl Section for TableSection {
+    fn render(&self, ctx: &mut RenderContext) -> Result<()> {
+        if self.table.rows.is_empty() {
+            return Err(RenderError::EmptyTable);
+        }
+        let mut table = self.table.clone();
+        table.position_y = ctx.y;
+        table.set_fonts(&ctx.regular, &ctx.bold);
+        ctx.page_count += table_page_count(&table, &ctx.page_size, ctx.y) - 1;
+        let (y, layer) = add_table(&mut table, &ctx.page_size, ctx.doc, ctx.layer.clone(), ctx.y, &ctx.regular, &ctx.bold);
+        ctx.layer = layer;
+        ctx.y = y - table.row_height * table.scale * 0.5 - SECTION_SPACING;
+        Ok(())
+    }
+}
+
+pub struct ParagraphSection {
+    pub text: String,
+    pub font_size: f64,
+    // What happens to tabs and other control characters in the text, see sanitize_text
+    pub control_char_policy: ControlCharPolicy
+}
+
+impl Section for ParagraphSection {
+    fn render(&self, ctx: &mut RenderContext) -> Result<()> {
+        let width = ctx.page_size.width - ctx.page_size.margin_width * 2.0;
+        let spacing = line_height(self.font_size);
+        for line in wrap_text(&sanitize_text(&self.text, self.control_char_policy), &ctx.regular, self.font_size, width) {
+            ctx.ensure_space(spacing);
+            ctx.y -= spacing;
+            ctx.layer.use_text(line, self.font_size, Mm(ctx.page_size.margin_width), Mm(ctx.y), &ctx.regular);
+        }
+        ctx.y -= SECTION_SPACING;
+        Ok(())
+    }
+}
+
+pub struct ImageSection {
+    pub image: printpdf::Image,
+    // Width to scale the image to, defaults to its size at 300dpi capped to the content width
+    pub width: Option<f64>
+}
+
+impl Section for ImageSection {
+    fn render(&self, ctx: &mut RenderContext) -> Result<()> {
+        let content_width = ctx.page_size.width - ctx.page_size.margin_width * 2.0;
+        let content_height = ctx.page_size.height - ctx.page_size.margin_height * 2.0;
+        let (image_width, image_height) = image_size_mm(&self.image, IMAGE_DPI);
+        let width = self.width.unwrap_or(image_width).min(content_width);
+        let scale = width / image_width;
+        let height = image_height * scale;
+        if height > content_height {
+            return Err(RenderError::ImageTooLarge);
+        }
+        ctx.ensure_space(height);
+        ctx.y -= height;
+        printpdf::Image::from(self.image.image.clone()).add_to_layer(ctx.layer.clone(), Some(Mm(ctx.page_size.margin_width)), Some(Mm(ctx.y)), None, Some(scale), Some(scale), Some(IMAGE_DPI));
+        ctx.y -= SECTION_SPACING;
+        Ok(())
+    }
+}
+
+pub struct PageBreak;
+
+impl Section for PageBreak {
+    fn render(&self, ctx: &mut RenderContext) -> Result<()> {
+        ctx.new_page();
+        Ok(())
+    }
+}
+
+// Renders sections one after another starting at the top of the given layer's page
+pub struct Document<'a> {
+    pub doc: &'a PdfDocumentReference,
+    pub page_size: PageSize,
+    pub regular: IndirectFontRef,
+    pub bold: IndirectFontRef
+}
+
+impl<'a> Document<'a> {
+    pub fn new(doc: &'a PdfDocumentReference, page_size: PageSize, regular: &IndirectFontRef, bold: &IndirectFontRef) -> Document<'a> {
+        Document {
+            doc,
+            page_size,
+            regular: regular.clone(),
+            bold: bold.clone()
+        }
+    }
+    pub fn render(&self, layer: PdfLayerReference, sections: &[&dyn Section]) -> Result<RenderContext<'a>> {
+        let mut ctx = RenderContext {
+            doc: self.doc,
+            page_size: self.page_size,
+            layer,
+            y: self.page_size.printable_top(),
+            regular: self.regular.clone(),
+            bold: self.bold.clone(),
+            page_count: 1
+        };
+        for section in sections {
+            section.render(&mut ctx)?;
+        }
+        Ok(ctx)
+    }
+}
+
+// Saves the document into memory instead of a file, e.g. to send it in a response
+pub fn render_to_bytes(doc: PdfDocumentReference) -> std::result::Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    let mut writer = BufWriter::new(&mut bytes);
+    doc.save(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    Ok(bytes)
+}
+
+// Like render_to_bytes, base64 encoded for embedding the PDF in JSON
+#[cfg(feature = "base64")]
+pub fn render_to_base64(doc: PdfDocumentReference) -> std::result::Result<String, Error> {
+    use base64::Engine;
+    Ok(base64::engine::general_purpose::STANDARD.encode(self::render_to_bytes(doc)?))
+}
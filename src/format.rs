@@ -0,0 +1,61 @@
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumberFormat {
+    pub thousands_separator: char,
+    pub decimal_separator: char,
+    pub grouping: usize,
+    pub decimals: usize
+}
+
+impl NumberFormat {
+    // 1,234.56
+    pub fn en_us() -> NumberFormat {
+        NumberFormat {
+            thousands_separator: ',',
+            decimal_separator: '.',
+            grouping: 3,
+            decimals: 2
+        }
+    }
+    // 1.234,56
+    pub fn de_de() -> NumberFormat {
+        NumberFormat {
+            thousands_separator: '.',
+            decimal_separator: ',',
+            grouping: 3,
+            decimals: 2
+        }
+    }
+    pub fn set_decimals(&mut self, decimals: usize) {
+        self.decimals = decimals;
+    }
+    pub fn format(&self, value: f64) -> String {
+        let fixed = format!("{:.*}", self.decimals, value.abs());
+        let (integer, fraction) = match fixed.find('.') {
+            Some(index) => (&fixed[..index], Some(&fixed[index + 1..])),
+            None => (fixed.as_str(), None)
+        };
+
+        let mut formatted = String::new();
+        if value < 0.0 && fixed.chars().any(|c| c != '0' && c != '.') {
+            formatted.push('-');
+        }
+        for (index, digit) in integer.chars().enumerate() {
+            let remaining = integer.len() - index;
+            if index > 0 && self.grouping > 0 && remaining % self.grouping == 0 {
+                formatted.push(self.thousands_separator);
+            }
+            formatted.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            formatted.push(self.decimal_separator);
+            formatted.push_str(fraction);
+        }
+        formatted
+    }
+}
+
+impl Default for NumberFormat {
+    fn default() -> NumberFormat {
+        NumberFormat::en_us()
+    }
+}
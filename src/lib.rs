@@ -1 +1,8 @@
-pub mod utils;
\ No newline at end of file
+pub mod chart;
+pub mod decorations;
+pub mod document;
+pub mod format;
+#[cfg(feature = "svg")]
+pub mod svg;
+pub mod text;
+pub mod utils;
@@ -0,0 +1,65 @@
+/* Third-Party crates */
+use printpdf::*;
+
+/* Local */
+use crate::text::PT_TO_MM;
+use crate::utils::{PageSize, Table};
+
+// Renders a table as add_table would lay it out, one SVG document per page, for quick previews.
+// Placements come from Table::layout_report so the two backends can't drift apart. Header text is
+// drawn unrotated, images and sparklines are left as empty boxes and borders are always drawn as
+// a full grid.
+pub fn table_to_svg(table: &Table, page_size: &PageSize) -> Vec<String> {
+    let report = table.layout_report(page_size);
+    // SVG measures y down from the top of the page
+    let flip = |y: f64| page_size.height - y;
+    let text_size = report.font_size * PT_TO_MM;
+
+    report.pages.iter().map(|page| {
+        let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\">\n", w = page_size.width, h = page_size.height);
+        let header_style = match page.page {
+            1 => &table.header_style,
+            _ => table.continuation_header_style.as_ref().unwrap_or(&table.header_style)
+        };
+        for row in page.rows.iter() {
+            let fill = match table.section_rows.get(&row.index) {
+                Some(section) => section.fill.as_ref(),
+                None if row.index == 0 => header_style.fill.as_ref(),
+                None => table.summary_row.as_ref().filter(|summary| summary.index == row.index).and_then(|summary| summary.style.fill.as_ref())
+            };
+            for cell in row.cells.iter() {
+                let y = flip(cell.y + cell.height);
+                if let Some(color) = fill {
+                    svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n", cell.x, y, cell.width, cell.height, self::svg_color(color)));
+                }
+                if table.borders {
+                    svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"{}\"/>\n", cell.x, y, cell.width, cell.height, PT_TO_MM));
+                }
+                let weight = match cell.bold {
+                    true => " font-weight=\"bold\"",
+                    false => ""
+                };
+                for (l_index, line) in cell.lines.iter().enumerate() {
+                    let baseline = flip(cell.text_y - l_index as f64 * report.line_height);
+                    svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-family=\"Helvetica, Arial, sans-serif\" font-size=\"{}\"{}>{}</text>\n", cell.text_x, baseline, text_size, weight, self::escape(line)));
+                }
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }).collect()
+}
+
+fn svg_color(color: &Color) -> String {
+    let (r, g, b) = match color {
+        Color::Rgb(rgb) => (rgb.r, rgb.g, rgb.b),
+        Color::Greyscale(grey) => (grey.percent, grey.percent, grey.percent),
+        Color::Cmyk(Cmyk { c, m, y, k, .. }) | Color::SpotColor(SpotColor { c, m, y, k }) => ((1.0 - c) * (1.0 - k), (1.0 - m) * (1.0 - k), (1.0 - y) * (1.0 - k))
+    };
+    let channel = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,348 @@
+/*Built-in*/
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/* Third-Party crates */
+use printpdf::*;
+
+/* Local */
+use crate::utils::PageSize;
+
+pub const PT_TO_MM: f64 = 25.4 / 72.0;
+pub const ELLIPSIS: &str = "\u{2026}";
+
+// Advance widths in 1/1000 em for the printable ASCII range (space to tilde)
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584
+];
+
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584
+];
+
+const TIMES_WIDTHS: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 278, 278, 564, 564, 564, 444,
+    921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722,
+    556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500,
+    333, 444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500,
+    500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541
+];
+
+enum Metrics {
+    Table(&'static [u16; 95], u16),
+    Monospace(u16)
+}
+
+fn metrics_for(font: &IndirectFontRef) -> Metrics {
+    let is = |name: &str| *font == IndirectFontRef::new(name);
+    if is("Courier") || is("Courier-Bold") || is("Courier-Oblique") || is("Courier-BoldOblique") {
+        Metrics::Monospace(600)
+    } else if is("Times-Roman") || is("Times-Bold") || is("Times-Italic") || is("Times-BoldItalic") {
+        Metrics::Table(&TIMES_WIDTHS, 500)
+    } else if is("Helvetica-Bold") || is("Helvetica-BoldOblique") {
+        Metrics::Table(&HELVETICA_BOLD_WIDTHS, 611)
+    } else {
+        Metrics::Table(&HELVETICA_WIDTHS, 556)
+    }
+}
+
+const BUILTIN_FONTS: [&str; 14] = [
+    "Times-Roman", "Times-Bold", "Times-Italic", "Times-BoldItalic",
+    "Helvetica", "Helvetica-Bold", "Helvetica-Oblique", "Helvetica-BoldOblique",
+    "Courier", "Courier-Bold", "Courier-Oblique", "Courier-BoldOblique",
+    "Symbol", "ZapfDingbats"
+];
+
+// The characters WinAnsiEncoding places in 0x80 to 0x9F, the rest of it matches Latin-1
+const WIN_ANSI_EXTRAS: &str = "\u{20AC}\u{201A}\u{0192}\u{201E}\u{2026}\u{2020}\u{2021}\u{02C6}\u{2030}\u{0160}\u{2039}\u{0152}\u{017D}\u{2018}\u{2019}\u{201C}\u{201D}\u{2022}\u{2013}\u{2014}\u{02DC}\u{2122}\u{0161}\u{203A}\u{0153}\u{017E}\u{0178}";
+
+// Whether the font can draw c. printpdf writes built-in fonts in WinAnsiEncoding, so they cover
+// printable ASCII, Latin-1 and the WinAnsi extras like the euro sign and dashes, anything else
+// comes out as a wrong glyph or nothing. External fonts are assumed to cover everything since
+// printpdf does not expose their glyph table.
+pub fn has_glyph(font: &IndirectFontRef, c: char) -> bool {
+    if !BUILTIN_FONTS.iter().any(|&name| *font == IndirectFontRef::new(name)) {
+        return true;
+    }
+    matches!(c as u32, 32..=126 | 160..=255) || WIN_ANSI_EXTRAS.contains(c)
+}
+
+// Draws text like use_text, switching each run of characters font has no glyph for (see has_glyph)
+// to the fallback font. Without a fallback the text is drawn in font as is.
+pub fn use_text_with_fallback(layer: &PdfLayerReference, text: &str, font_size: f64, x: f64, y: f64, font: &IndirectFontRef, fallback: Option<&IndirectFontRef>) {
+    let fallback = match fallback {
+        Some(fallback) if !text.chars().all(|c| self::has_glyph(font, c)) => fallback,
+        _ => {
+            layer.use_text(text, font_size, Mm(x), Mm(y), font);
+            return;
+        }
+    };
+    let mut runs: Vec<(String, bool)> = Vec::new();
+    for c in text.chars() {
+        let covered = self::has_glyph(font, c);
+        match runs.last_mut() {
+            Some((run, run_covered)) if *run_covered == covered => run.push(c),
+            _ => runs.push((c.to_string(), covered))
+        }
+    }
+    let mut run_x = x;
+    for (run, covered) in runs {
+        let run_font = match covered {
+            true => font,
+            false => fallback
+        };
+        layer.use_text(run.as_str(), font_size, Mm(run_x), Mm(y), run_font);
+        run_x += self::text_width(&run, run_font, font_size);
+    }
+}
+
+type WidthKey = (String, IndirectFontRef, u64);
+
+thread_local! {
+    // Widths measured while a MeasureCache is alive, None outside of one
+    static WIDTH_CACHE: RefCell<Option<HashMap<WidthKey, f64>>> = const { RefCell::new(None) };
+}
+
+// Remembers every text_width result until it is dropped, so the measuring and drawing passes of a
+// table don't work out the same widths again. Scopes opened while another is alive share its cache,
+// which is cleared when the outermost one is dropped.
+pub(crate) struct MeasureCache {
+    outermost: bool
+}
+
+impl MeasureCache {
+    pub(crate) fn scope() -> MeasureCache {
+        let outermost = WIDTH_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            let outermost = cache.is_none();
+            if outermost {
+                *cache = Some(HashMap::new());
+            }
+            outermost
+        });
+        MeasureCache { outermost }
+    }
+}
+
+impl Drop for MeasureCache {
+    fn drop(&mut self) {
+        if self.outermost {
+            WIDTH_CACHE.with(|cache| *cache.borrow_mut() = None);
+        }
+    }
+}
+
+// Estimates the rendered width of a string in mm. Widths come from the built-in font
+// metrics, external fonts are measured as Helvetica since printpdf does not expose their glyphs.
+pub fn text_width(text: &str, font: &IndirectFontRef, font_size: f64) -> f64 {
+    WIDTH_CACHE.with(|cache| match cache.borrow_mut().as_mut() {
+        Some(widths) => *widths.entry((text.to_string(), font.clone(), font_size.to_bits())).or_insert_with(|| self::measure_text(text, font, font_size)),
+        None => self::measure_text(text, font, font_size)
+    })
+}
+
+fn measure_text(text: &str, font: &IndirectFontRef, font_size: f64) -> f64 {
+    let units: u32 = match self::metrics_for(font) {
+        Metrics::Monospace(width) => text.chars().count() as u32 * width as u32,
+        Metrics::Table(widths, fallback) => text.chars().map(|c| {
+            match c as u32 {
+                32..=126 => widths[(c as u32 - 32) as usize] as u32,
+                0x2026 => 1000,
+                _ => fallback as u32
+            }
+        }).sum()
+    };
+    units as f64 / 1000.0 * font_size * PT_TO_MM
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add_leader_line(layer: &PdfLayerReference, left_text: &str, right_text: &str, x: f64, y: f64, width: f64, font: &IndirectFontRef, font_size: f64, leader_char: char) {
+    let left_width = self::text_width(left_text, font, font_size);
+    let right_width = self::text_width(right_text, font, font_size);
+    let space = self::text_width(" ", font, font_size);
+    let leader_width = self::text_width(&leader_char.to_string(), font, font_size);
+
+    layer.use_text(left_text, font_size, Mm(x), Mm(y), font);
+    layer.use_text(right_text, font_size, Mm(x + width - right_width), Mm(y), font);
+
+    let gap = width - left_width - right_width - space * 2.0;
+    if leader_width > 0.0 && gap > leader_width {
+        let count = (gap / leader_width).floor() as usize;
+        let leader: String = std::iter::repeat_n(leader_char, count).collect();
+        // right-align the leaders so they finish one space before the right text
+        let leader_x = x + width - right_width - space - count as f64 * leader_width;
+        layer.use_text(leader, font_size, Mm(leader_x), Mm(y), font);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right
+}
+
+// Returns the x at which to start drawing text so that it is aligned relative to x
+pub fn aligned_x(text: &str, x: f64, align: Alignment, font: &IndirectFontRef, font_size: f64) -> f64 {
+    match align {
+        Alignment::Left => x,
+        Alignment::Center => x - self::text_width(text, font, font_size) / 2.0,
+        Alignment::Right => x - self::text_width(text, font, font_size)
+    }
+}
+
+// Draws each line aligned relative to x, with line_spacing mm between baselines.
+// Returns the baseline the next line would have been drawn at.
+#[allow(clippy::too_many_arguments)]
+pub fn add_text_block(layer: &PdfLayerReference, lines: &[String], x: f64, y: f64, align: Alignment, font: &IndirectFontRef, font_size: f64, line_spacing: f64) -> f64 {
+    let mut current_y = y;
+    for line in lines {
+        let line_x = self::aligned_x(line, x, align, font, font_size);
+        layer.use_text(line.as_str(), font_size, Mm(line_x), Mm(current_y), font);
+        current_y -= line_spacing;
+    }
+    current_y
+}
+
+// Draws text rotated counter-clockwise by angle degrees around its origin at (x, y)
+pub fn use_rotated_text(layer: &PdfLayerReference, text: &str, font_size: f64, x: f64, y: f64, angle: f64, font: &IndirectFontRef) {
+    // Td moves in the rotated text space, so the page position is rotated back first
+    let radians = angle.to_radians();
+    let cursor_x = x * radians.cos() + y * radians.sin();
+    let cursor_y = -x * radians.sin() + y * radians.cos();
+
+    layer.begin_text_section();
+    layer.set_font(font, font_size);
+    layer.set_text_matrix(TextMatrix::Rotate(angle));
+    layer.set_text_cursor(Mm(cursor_x), Mm(cursor_y));
+    layer.write_text(text, font);
+    layer.end_text_section();
+}
+
+// Breaks text into lines no wider than width, splitting on whitespace. Words wider than the
+// line are kept whole on their own line. Explicit newlines always start a new line.
+pub fn wrap_text(text: &str, font: &IndirectFontRef, font_size: f64, width: f64) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = match line.is_empty() {
+                true => word.to_string(),
+                false => format!("{} {}", line, word)
+            };
+            if !line.is_empty() && self::text_width(&candidate, font, font_size) > width {
+                lines.push(line);
+                line = word.to_string();
+            } else {
+                line = candidate;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// Wraps like wrap_text but keeps at most max_lines lines, the last one ending in an ellipsis
+// when text was cut off
+pub fn wrap_text_lines(text: &str, font: &IndirectFontRef, font_size: f64, width: f64, max_lines: usize) -> Vec<String> {
+    self::wrap_text_lines_with(text, font, font_size, width, max_lines, ELLIPSIS)
+}
+
+// Like wrap_text_lines with another indicator for cut off text, e.g. "..." or ">>"
+pub fn wrap_text_lines_with(text: &str, font: &IndirectFontRef, font_size: f64, width: f64, max_lines: usize, indicator: &str) -> Vec<String> {
+    let mut lines = self::wrap_text(text, font, font_size, width);
+    let max_lines = max_lines.max(1);
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = lines.pop().unwrap();
+        lines.push(self::ellipsize_with(&last, font, font_size, width, indicator));
+    }
+    lines
+}
+
+// Appends an ellipsis, dropping characters from the end until the result fits in width
+pub fn ellipsize(text: &str, font: &IndirectFontRef, font_size: f64, width: f64) -> String {
+    self::ellipsize_with(text, font, font_size, width, ELLIPSIS)
+}
+
+// Like ellipsize with another indicator in place of the ellipsis
+pub fn ellipsize_with(text: &str, font: &IndirectFontRef, font_size: f64, width: f64, indicator: &str) -> String {
+    let mut chars: Vec<char> = text.chars().collect();
+    loop {
+        let candidate = format!("{}{}", chars.iter().collect::<String>().trim_end(), indicator);
+        if chars.is_empty() || self::text_width(&candidate, font, font_size) <= width {
+            return candidate;
+        }
+        chars.pop();
+    }
+}
+
+// What sanitize_text does with control characters, the Unicode Cc category: U+0000 to U+001F and
+// U+007F to U+009F. Built-in fonts have no glyphs for them, so they show up as odd marks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlCharPolicy {
+    Keep,
+    // Tabs become tab_width spaces, the other control characters are removed
+    Strip { tab_width: usize },
+    // Tabs become tab_width spaces, the other control characters apart from \r become the replacement
+    Replace { tab_width: usize, replacement: char }
+}
+
+// Applies the policy to text. Newlines are always kept since wrap_text and add_flowing_text break
+// lines there, and \r is dropped by Strip and Replace alike, so "\r\n" line endings come out as
+// "\n" rather than a replacement before each newline. Tables apply it to every cell with
+// Table::set_control_char_policy, paragraphs to their text.
+pub fn sanitize_text(text: &str, policy: ControlCharPolicy) -> String {
+    let (tab_width, replacement) = match policy {
+        ControlCharPolicy::Keep => return text.to_string(),
+        ControlCharPolicy::Strip { tab_width } => (tab_width, None),
+        ControlCharPolicy::Replace { tab_width, replacement } => (tab_width, Some(replacement))
+    };
+    let mut sanitized = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => sanitized.push(c),
+            '\t' => sanitized.extend(std::iter::repeat_n(' ', tab_width)),
+            '\r' => (),
+            c if c.is_control() => sanitized.extend(replacement),
+            c => sanitized.push(c)
+        }
+    }
+    sanitized
+}
+
+// Distance between baselines for a font size, in mm
+pub fn line_height(font_size: f64) -> f64 {
+    font_size * 1.2 * PT_TO_MM
+}
+
+// Wraps text to width and draws it line by line from the baseline y, continuing at the top of a
+// new page when a line would fall below the bottom margin. Returns the baseline after the last
+// line and the layer it was drawn on, like add_table. The text is sanitized with policy first.
+#[allow(clippy::too_many_arguments)]
+pub fn add_flowing_text(doc: &PdfDocumentReference, page_size: &PageSize, current_layer_ref: PdfLayerReference, text: &str, x: f64, y: f64, width: f64, font: &IndirectFontRef, font_size: f64, policy: ControlCharPolicy) -> (f64, PdfLayerReference) {
+    let spacing = self::line_height(font_size);
+    let mut layer = current_layer_ref;
+    let mut baseline = y;
+    for line in self::wrap_text(&self::sanitize_text(text, policy), font, font_size, width) {
+        if baseline < page_size.margin_height {
+            let (new_page, new_layer) = doc.add_page(Mm(page_size.width), Mm(page_size.height), "Text");
+            layer = doc.get_page(new_page).get_layer(new_layer);
+            baseline = page_size.printable_top() - spacing;
+        }
+        layer.use_text(line, font_size, Mm(x), Mm(baseline), font);
+        baseline -= spacing;
+    }
+    (baseline, layer)
+}
@@ -1,12 +1,35 @@
 /*Built-in*/
-use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::io::Cursor;
+use std::rc::Rc;
 
 /* Third-Party crates */
 use bmp::{Image, Pixel};
 use printpdf::*;
-use barcoders::sym::code128::*;
+use barcoders::sym::code128::Code128;
+use barcoders::sym::code39::Code39;
+use barcoders::sym::ean13::EAN13;
 
+/* Local */
+use crate::text::{aligned_x, line_height, text_width, use_rotated_text, use_text_with_fallback, sanitize_text, wrap_text_lines_with, Alignment, ControlCharPolicy, MeasureCache, ELLIPSIS, PT_TO_MM};
+
+// Resolution images are placed at, shared by every module that sizes or draws images
+pub(crate) const IMAGE_DPI: f64 = 300.0;
+const PAGE_BREAK_RESERVE: f64 = 7.5;
+// Distance between the two lines of a double rule in mm
+const DOUBLE_RULE_GAP: f64 = 0.6;
+// Share of a line's box (text width by font size) that glyphs typically cover with ink
+const TEXT_INK_RATIO: f64 = 0.2;
+// Height of the tallest glyphs above the baseline, in em
+const ASCENT: f64 = 0.75;
+// Space between a cell icon and the text after it in mm
+const ICON_GAP: f64 = 1.0;
+const DEFAULT_FONT_SIZE: f64 = 12.0;
+// Narrowest a column is drawn at scale 1.0 unless it sets its own min_width_mm
+pub const MIN_COLUMN_WIDTH_MM: f64 = 5.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct PageSize {
     pub width: f64,
     pub height: f64,
@@ -17,7 +40,7 @@ pub struct PageSize {
 #[allow(dead_code)]
 #[allow(non_snake_case)]
 impl PageSize {
-    pub fn A1() -> PageSize {
+    pub const fn A1() -> PageSize {
         PageSize {
             width: 594.0,
             height: 841.0,
@@ -25,7 +48,7 @@ impl PageSize {
             margin_height: 10.0
         }
     }
-    pub fn A2() -> PageSize {
+    pub const fn A2() -> PageSize {
         PageSize {
             width: 420.0,
             height: 594.0,
@@ -33,7 +56,7 @@ impl PageSize {
             margin_height: 10.0
         }
     }
-    pub fn A3() -> PageSize {
+    pub const fn A3() -> PageSize {
         PageSize {
             width: 297.0,
             height: 420.0,
@@ -41,7 +64,7 @@ impl PageSize {
             margin_height: 10.0
         }
     }
-    pub fn A4() -> PageSize {
+    pub const fn A4() -> PageSize {
         PageSize {
             width: 210.0,
             height: 297.0,
@@ -49,7 +72,7 @@ impl PageSize {
             margin_height: 10.0
         }
     }
-    pub fn A5() -> PageSize {
+    pub const fn A5() -> PageSize {
         PageSize {
             width: 148.0,
             height: 210.0,
@@ -57,54 +80,1210 @@ impl PageSize {
             margin_height: 10.0
         }
     }
+    pub fn all_formats() -> &'static [(&'static str, PageSize)] {
+        &PAGE_FORMATS
+    }
+    // The format name, e.g. "A4", or the size in mm for custom pages
+    pub fn name(&self) -> String {
+        match PAGE_FORMATS.iter().find(|(_, format)| format.width == self.width && format.height == self.height) {
+            Some((name, _)) => name.to_string(),
+            None => format!("{}x{}mm", self.width, self.height)
+        }
+    }
+    // Returns a copy with margins set to a percentage of the width and height, e.g. 5.0 for 5%.
+    // The margins are stored as absolute mm, so they don't follow later changes to the size.
+    pub fn with_margin_pct(&self, w_pct: f64, h_pct: f64) -> PageSize {
+        PageSize {
+            margin_width: self.width * w_pct / 100.0,
+            margin_height: self.height * h_pct / 100.0,
+            ..*self
+        }
+    }
+    pub fn printable_top(&self) -> f64 {
+        self.height - self.margin_height
+    }
+    // Tables start a new page once they reach this y, leaving room for one default row above the margin
+    pub fn printable_bottom(&self) -> f64 {
+        self.margin_height + PAGE_BREAK_RESERVE
+    }
+    // Returns the (x, y, width, height) of the area inside the margins in PDF points, measured from
+    // the bottom-left corner like printpdf's annotation coordinates
+    pub fn printable_rect_pt(&self) -> (f64, f64, f64, f64) {
+        let pt = |mm: f64| Pt::from(Mm(mm)).0;
+        (pt(self.margin_width), pt(self.margin_height), pt(self.width - self.margin_width * 2.0), pt(self.height - self.margin_height * 2.0))
+    }
+    // Both bounds are inclusive, so whatever clamp_to_printable returns is in the printable area.
+    // A table drawn down to exactly printable_bottom still breaks there and continues on a new page.
+    pub fn is_in_printable_area(&self, y: f64) -> bool {
+        y >= self.printable_bottom() && y <= self.printable_top()
+    }
+    pub fn clamp_to_printable(&self, y: f64) -> f64 {
+        y.min(self.printable_top()).max(self.printable_bottom())
+    }
 }    
 
+static PAGE_FORMATS: [(&str, PageSize); 5] = [
+    ("A1", PageSize::A1()),
+    ("A2", PageSize::A2()),
+    ("A3", PageSize::A3()),
+    ("A4", PageSize::A4()),
+    ("A5", PageSize::A5())
+];
+
+#[derive(Clone, Debug)]
+pub struct TextRun {
+    pub text: String,
+    pub bold: bool,
+    pub color: Option<Color>,
+    pub script: Script,
+    // Size in points at scale 1, None for the table's font size
+    pub font_size: Option<f64>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Script {
+    Normal,
+    Superscript,
+    Subscript
+}
+
+impl TextRun {
+    pub fn new(text: &str) -> TextRun {
+        TextRun {
+            text: text.to_string(),
+            bold: false,
+            color: None,
+            script: Script::Normal,
+            font_size: None
+        }
+    }
+    pub fn bold(text: &str) -> TextRun {
+        TextRun {
+            bold: true,
+            ..TextRun::new(text)
+        }
+    }
+    // e.g. the "2" in m², drawn smaller and raised
+    pub fn superscript(text: &str) -> TextRun {
+        TextRun {
+            script: Script::Superscript,
+            ..TextRun::new(text)
+        }
+    }
+    // e.g. the "2" in H₂O, drawn smaller and lowered
+    pub fn subscript(text: &str) -> TextRun {
+        TextRun {
+            script: Script::Subscript,
+            ..TextRun::new(text)
+        }
+    }
+    // Drawn at its own size, the row's baseline moves down so the larger text still fits
+    pub fn sized(text: &str, font_size: f64) -> TextRun {
+        TextRun {
+            font_size: Some(font_size),
+            ..TextRun::new(text)
+        }
+    }
+    // Returns the font size for the run and how far its baseline is raised, in mm. font_size is the
+    // table's, used unless the run has its own.
+    fn size_and_rise(&self, font_size: f64, scale: f64) -> (f64, f64) {
+        let font_size = self.font_size.map(|size| size * scale).unwrap_or(font_size);
+        let em = font_size * PT_TO_MM;
+        match self.script {
+            Script::Normal => (font_size, 0.0),
+            Script::Superscript => (font_size * 0.65, em * 0.35),
+            Script::Subscript => (font_size * 0.65, -em * 0.15)
+        }
+    }
+}
+
+pub enum CellContent {
+    Text(String),
+    Image(printpdf::Image),
+    // Runs drawn one after another, each in its own weight and colour
+    Runs(Vec<TextRun>),
+    // A line through the values across the cell, the smallest at the bottom and the largest at the top
+    Sparkline(Vec<f64>)
+}
+
+impl Clone for CellContent {
+    fn clone(&self) -> CellContent {
+        match self {
+            CellContent::Text(text) => CellContent::Text(text.clone()),
+            CellContent::Runs(runs) => CellContent::Runs(runs.clone()),
+            CellContent::Sparkline(values) => CellContent::Sparkline(values.clone()),
+            CellContent::Image(image) => CellContent::Image(printpdf::Image::from(image.image.clone()))
+        }
+    }
+}
+
+impl From<String> for CellContent {
+    fn from(text: String) -> CellContent {
+        CellContent::Text(text)
+    }
+}
+
+impl From<&str> for CellContent {
+    fn from(text: &str) -> CellContent {
+        CellContent::Text(text.to_string())
+    }
+}
+
+impl From<Vec<TextRun>> for CellContent {
+    fn from(runs: Vec<TextRun>) -> CellContent {
+        CellContent::Runs(runs)
+    }
+}
+
+impl From<Vec<f64>> for CellContent {
+    fn from(values: Vec<f64>) -> CellContent {
+        CellContent::Sparkline(values)
+    }
+}
+
+impl From<printpdf::Image> for CellContent {
+    fn from(image: printpdf::Image) -> CellContent {
+        CellContent::Image(image)
+    }
+}
+
+#[derive(Clone)]
 pub struct Table {
-    pub rows: Vec<Vec<String>>,
+    pub rows: Vec<Vec<CellContent>>,
     pub columns: Vec<Column>,
     pub position_y: f64,
     pub max_columns: usize,
+    pub max_columns_set: bool,
     pub borders: bool,
-    pub row_height: f64
+    pub row_height: f64,
+    pub snap_to: Option<f64>,
+    pub caption: Option<String>,
+    pub scale: f64,
+    pub page_callback: Option<Rc<PageCallback>>,
+    pub row_heights: Vec<Option<f64>>,
+    pub section_rows: HashMap<usize, SectionRow>,
+    pub regular_font: IndirectFontRef,
+    pub bold_font: IndirectFontRef,
+    pub horizontal_paginate: bool,
+    pub repeat_column: Option<usize>,
+    pub shrink_to_fit_width: bool,
+    pub empty_placeholder: Option<String>,
+    pub cell_gutter: f64,
+    pub header_style: HeaderStyle,
+    pub continuation_header_style: Option<HeaderStyle>,
+    pub max_row_height: Option<f64>,
+    pub skip_header_before_section: bool,
+    pub position_anchor: PositionAnchor,
+    pub title: Option<String>,
+    pub struck_rows: HashSet<usize>,
+    pub struck_text_color: Option<Color>,
+    pub cell_transform: Option<Rc<CellTransform>>,
+    pub header_rule: Option<Rule>,
+    pub column_emphasis: Option<ColumnEmphasis>,
+    pub row_y_positions: Vec<f64>,
+    pub rtl_cells: HashSet<(usize, usize)>,
+    pub image_rows: HashMap<usize, ImageRow>,
+    pub kept_groups: Vec<(usize, usize)>,
+    pub fallback_font: Option<IndirectFontRef>,
+    pub summary_row: Option<SummaryRow>,
+    pub border_style: BorderStyle,
+    pub dividers_below_header: bool,
+    pub cell_icons: HashMap<(usize, usize), ImageXObject>,
+    pub horizontal_align: Alignment,
+    pub ellipsis: String,
+    pub merge_vertical_columns: Vec<usize>,
+    pub min_rows_on_last_page: usize,
+    pub borders_only_filled: bool,
+    pub header_offset: Option<f64>,
+    pub outer_border_style: Option<LineStyle>,
+    pub column_spacing: f64,
+    pub layer_name: Option<String>,
+    pub control_char_policy: ControlCharPolicy,
+    // Column index in the original table of each column of a select_columns copy, empty otherwise
+    pub source_columns: Vec<usize>
+}
+
+// How a line is stroked, dash and gap lengths being in points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineStyle {
+    Solid,
+    Dashed { dash: i64, gap: i64 }
+}
+
+// Which edges of each cell are drawn when borders are on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BorderStyle {
+    Grid,
+    VerticalOnly
+}
+
+// Marks columns with a background behind the body cells and/or a rule along their left edge
+#[derive(Clone)]
+pub struct ColumnEmphasis {
+    pub columns: Vec<usize>,
+    pub fill: Option<Color>,
+    pub left_rule: Option<Rule>
+}
+
+// A line with its own thickness in points and colour
+#[derive(Clone)]
+pub struct Rule {
+    pub thickness: f64,
+    pub color: Color
 }
 
+// What position_y refers to. By default it sits half a row above the header's top edge, so the
+// header's baseline is one row height (plus the cell padding with borders) below position_y.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PositionAnchor {
+    HalfRowAbove,
+    HeaderTop,
+    FirstBaseline
+}
+
+// Where Table::place puts the table on the page
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerticalAnchor {
+    Top,
+    Center,
+    Bottom
+}
+
+// How the header row is drawn, e.g. filled on the first page and plain where it is repeated
+#[derive(Clone)]
+pub struct HeaderStyle {
+    pub fill: Option<Color>,
+    pub bold: bool,
+    pub text_color: Option<Color>
+}
+
+impl Default for HeaderStyle {
+    fn default() -> HeaderStyle {
+        HeaderStyle {
+            fill: None,
+            bold: true,
+            text_color: None
+        }
+    }
+}
+
+// A full-width divider row labelled with the first cell of its row, e.g. "North Region"
+#[derive(Clone)]
+pub struct SectionRow {
+    pub fill: Option<Color>
+}
+
+// A totals row near the top of the table styled apart from the header and body, optionally with a
+// double rule along its top edge
+#[derive(Clone)]
+pub struct SummaryRow {
+    pub index: usize,
+    pub style: HeaderStyle,
+    pub double_rule: Option<Rule>
+}
+
+// A row holding one image stretched across the whole table, height mm tall before scaling
+#[derive(Clone)]
+pub struct ImageRow {
+    pub height: f64
+}
+
+// Called after each page of a table is drawn with the layer, the page number and the total page count
+pub type PageCallback = dyn Fn(&PdfLayerReference, usize, usize);
+
+// Rewrites the text of a cell given its row and column index, e.g. to uppercase headers or mask a column
+pub type CellTransform = dyn Fn(usize, usize, &str) -> String;
+
 impl Table {
     pub fn default(position: f64) -> Table {
-        return Table {
-            columns: vec![Column {width: 6}, Column {width: 2}, Column {width: 2}, Column {width: 2}],
-            rows: Vec::<Vec<String>>::new(),
+        Table {
+            columns: vec![Column {width: 6, ..Column::default()}, Column {width: 2, ..Column::default()}, Column {width: 2, ..Column::default()}, Column {width: 2, ..Column::default()}],
+            rows: Vec::<Vec<CellContent>>::new(),
             position_y: position,
             max_columns: 12,
+            max_columns_set: false,
             borders: false,
-            row_height: 7.5
+            row_height: 7.5,
+            snap_to: None,
+            caption: None,
+            scale: 1.0,
+            page_callback: None,
+            row_heights: Vec::new(),
+            section_rows: HashMap::new(),
+            regular_font: IndirectFontRef::new("Helvetica"),
+            bold_font: IndirectFontRef::new("Helvetica-Bold"),
+            horizontal_paginate: false,
+            repeat_column: None,
+            shrink_to_fit_width: false,
+            empty_placeholder: None,
+            cell_gutter: 0.0,
+            header_style: HeaderStyle::default(),
+            continuation_header_style: None,
+            max_row_height: None,
+            skip_header_before_section: false,
+            position_anchor: PositionAnchor::HalfRowAbove,
+            title: None,
+            struck_rows: HashSet::new(),
+            struck_text_color: None,
+            cell_transform: None,
+            header_rule: None,
+            column_emphasis: None,
+            row_y_positions: Vec::new(),
+            rtl_cells: HashSet::new(),
+            image_rows: HashMap::new(),
+            kept_groups: Vec::new(),
+            fallback_font: None,
+            summary_row: None,
+            border_style: BorderStyle::Grid,
+            dividers_below_header: false,
+            cell_icons: HashMap::new(),
+            horizontal_align: Alignment::Left,
+            ellipsis: ELLIPSIS.to_string(),
+            merge_vertical_columns: Vec::new(),
+            min_rows_on_last_page: 0,
+            borders_only_filled: false,
+            header_offset: None,
+            outer_border_style: None,
+            column_spacing: 0.0,
+            layer_name: None,
+            control_char_policy: ControlCharPolicy::Keep,
+            source_columns: Vec::new()
         }
     }
+    // A week planner starting on start_date: seven equal columns headed by the day names, over a row
+    // with the dates. Add rows for the entries, or set_row_heights to leave room to write.
+    #[cfg(feature = "chrono")]
+    pub fn week_grid(position: f64, start_date: chrono::NaiveDate) -> Table {
+        let mut table = Table::default(position);
+        table.equal_columns(7);
+        let days: Vec<chrono::NaiveDate> = start_date.iter_days().take(7).collect();
+        table.add_row(days.iter().map(|day| day.format("%a").to_string()).collect());
+        table.add_row(days.iter().map(|day| day.format("%-d %b").to_string()).collect());
+        table
+    }
     pub fn set_borders(&mut self, borders_on: bool) {
         self.borders = borders_on;
     }
-    pub fn add_row(&mut self, row: Vec<String>) {
-        self.rows.push(row);
+    // Where a table narrower than the space between the margins sits, e.g. Right for a totals box
+    // against the right margin
+    pub fn set_horizontal_align(&mut self, align: Alignment) {
+        self.horizontal_align = align;
+    }
+    // Indicator ending cell text cut off by a column's max_lines, an ellipsis by default. Use "..." for
+    // fonts without the ellipsis glyph.
+    pub fn set_ellipsis(&mut self, ellipsis: &str) {
+        self.ellipsis = ellipsis.to_string();
+    }
+    // In these columns runs of body rows with the same text are drawn as one cell, the text centred
+    // between the top and bottom of the run. A run crossing a page break is split there.
+    pub fn set_merge_vertical_columns(&mut self, columns: Vec<usize>) {
+        self.merge_vertical_columns = columns;
+    }
+    // Whether the cell is left to draw_merged_cells rather than drawn with its row
+    fn is_merged_cell(&self, row_index: usize, column_index: usize) -> bool {
+        row_index != 0 && self.merge_vertical_columns.contains(&column_index) && !self.is_full_width(row_index) && self.summary_style(row_index).is_none()
+            && matches!(self.rows[row_index].get(column_index), Some(CellContent::Text(_)))
+    }
+    // Runs of rows on a page sharing the text in a merged column, as (first, last) positions in rows
+    fn merged_runs(&self, rows: &[PlacedRow], column_index: usize) -> Vec<(usize, usize)> {
+        let text = |position: usize| match self.rows[rows[position].index].get(column_index) {
+            Some(CellContent::Text(text)) => Some(text),
+            _ => None
+        };
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for position in (0..rows.len()).filter(|&p| self.is_merged_cell(rows[p].index, column_index)) {
+            match runs.last_mut() {
+                Some((first, last)) if *last + 1 == position && text(*first) == text(position) => *last = position,
+                _ => runs.push((position, position))
+            }
+        }
+        runs
+    }
+    // Orphan control: when fewer rows than this would continue onto the last page, the page before
+    // breaks earlier to hand it some more. 0 leaves the breaks alone.
+    pub fn set_min_rows_on_last_page(&mut self, rows: usize) {
+        self.min_rows_on_last_page = rows;
+    }
+    // Skips the border of cells left blank, the empty_placeholder counting as content. Edges shared
+    // with a cell that has content are still drawn as part of its box.
+    pub fn set_borders_only_filled(&mut self, only_filled: bool) {
+        self.borders_only_filled = only_filled;
+    }
+    fn is_blank_cell(&self, row_index: usize, column_index: usize) -> bool {
+        let blank = match self.rows[row_index].get(column_index) {
+            Some(CellContent::Text(text)) => text.trim().is_empty(),
+            Some(CellContent::Runs(runs)) => runs.iter().all(|run| run.text.trim().is_empty()),
+            Some(CellContent::Image(_)) => false,
+            Some(CellContent::Sparkline(values)) => values.is_empty(),
+            None => true
+        };
+        blank && self.empty_placeholder.is_none()
+    }
+    // Puts the header's baseline this many mm above the top of the first body row, moving only the
+    // header. None leaves the header in the row above the body.
+    pub fn set_header_offset(&mut self, offset: Option<f64>) {
+        self.header_offset = offset;
+    }
+    // Top of the header's box when the rows it heads start below it at top
+    fn header_top(&self, page_size: &PageSize, top: f64) -> f64 {
+        match self.header_offset {
+            Some(offset) => self.top_for_baseline(top - self.height_of_row(page_size, 0) + offset) + self.baseline_drop(0),
+            None => top
+        }
     }
+    // Draws a frame around the rows on each page in its own style, e.g. dashed as a cut line around a
+    // coupon, while the cell borders stay solid
+    pub fn set_outer_border_style(&mut self, style: Option<LineStyle>) {
+        self.outer_border_style = style;
+    }
+    // Gap in mm left between neighbouring columns, taken out of the columns in proportion to their
+    // widths so the table keeps its width. Unlike cell_gutter the cells keep their full height.
+    pub fn set_column_spacing(&mut self, spacing: f64) {
+        self.column_spacing = spacing;
+    }
+    // Draws the table on a new layer with this name on every page it covers instead of the layer
+    // passed to add_table, so viewers can show or hide it on its own. add_table returns that layer.
+    pub fn set_layer_name(&mut self, name: Option<String>) {
+        self.layer_name = name;
+    }
+    // Cleans tabs and other control characters out of cell text before it is drawn, see sanitize_text
+    pub fn set_control_char_policy(&mut self, policy: ControlCharPolicy) {
+        self.control_char_policy = policy;
+    }
+    pub fn set_border_style(&mut self, style: BorderStyle) {
+        self.border_style = style;
+    }
+    // Leaves the vertical lines out of the header row so they only divide the body, e.g. under a
+    // filled header
+    pub fn set_dividers_below_header(&mut self, below_header: bool) {
+        self.dividers_below_header = below_header;
+    }
+    pub fn add_row<T: Into<CellContent>>(&mut self, row: Vec<T>) {
+        self.rows.push(row.into_iter().map(|cell| cell.into()).collect());
+    }
+    pub fn add_section_row(&mut self, label: &str, fill: Option<Color>) {
+        self.section_rows.insert(self.rows.len(), SectionRow { fill });
+        self.rows.push(vec![CellContent::from(label)]);
+    }
+    // Adds a row that draws the image over the full table width at the given height, e.g. a chart
+    // between data rows. It moves to the next page whole when it doesn't fit.
+    pub fn add_image_row(&mut self, image: printpdf::Image, height: f64) {
+        self.image_rows.insert(self.rows.len(), ImageRow { height });
+        self.rows.push(vec![CellContent::Image(image)]);
+    }
+    fn is_full_width(&self, row_index: usize) -> bool {
+        self.section_rows.contains_key(&row_index) || self.image_rows.contains_key(&row_index)
+    }
+    // Unless set_columns_len was called, max_columns follows the column widths so they fill the page
     pub fn set_columns(&mut self, columns: Vec<Column>) {
         self.columns = columns;
+        if !self.max_columns_set {
+            self.fit_columns();
+        }
+    }
+    // Replaces the columns with n of the same width spread over the full page width
+    pub fn equal_columns(&mut self, n: usize) {
+        self.columns = vec![Column { width: 1, ..Column::default() }; n];
+        self.fit_columns();
     }
     pub fn set_columns_len(&mut self, columns: usize) {
         self.max_columns = columns;
+        self.max_columns_set = true;
+    }
+    pub fn fit_columns(&mut self) {
+        self.max_columns = self.columns.iter().map(|c| c.width).sum::<usize>().max(1);
     }
     pub fn set_row_height(&mut self, row_height: f64) {
         self.row_height = row_height;
     }
+    pub fn set_snap_to(&mut self, grid: Option<f64>) {
+        self.snap_to = grid;
+    }
+    pub fn set_caption(&mut self, caption: String) {
+        self.caption = Some(caption);
+    }
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale;
+    }
+    pub fn set_row_heights(&mut self, row_heights: Vec<Option<f64>>) {
+        self.row_heights = row_heights;
+    }
+    // Fonts used when measuring text, add_table sets these to the fonts it draws with
+    pub fn set_fonts(&mut self, regular: &IndirectFontRef, bold: &IndirectFontRef) {
+        self.regular_font = regular.clone();
+        self.bold_font = bold.clone();
+    }
+    // Draws the row with its own fill, weight and text colour, e.g. a totals row right below the header
+    pub fn mark_summary_row(&mut self, index: usize, style: HeaderStyle, double_rule: Option<Rule>) {
+        self.summary_row = Some(SummaryRow { index, style, double_rule });
+    }
+    fn summary_style(&self, row_index: usize) -> Option<&HeaderStyle> {
+        self.summary_row.as_ref().filter(|summary| summary.index == row_index && row_index != 0).map(|summary| &summary.style)
+    }
+    fn is_bold_row(&self, row_index: usize) -> bool {
+        row_index == 0 || self.summary_style(row_index).is_some_and(|style| style.bold)
+    }
+    // Font for the characters the regular and bold fonts have no glyph for, checked one character at
+    // a time with text::has_glyph, e.g. an external font with the symbols your data contains
+    pub fn set_fallback_font(&mut self, fallback: Option<&IndirectFontRef>) {
+        self.fallback_font = fallback.cloned();
+    }
+    pub fn set_page_callback<F: Fn(&PdfLayerReference, usize, usize) + 'static>(&mut self, callback: F) {
+        self.page_callback = Some(Rc::new(callback));
+    }
+    // Columns wider than max_columns in total are split across pages instead of running off the
+    // right edge. repeat_column, e.g. a label column, is then drawn first on every page.
+    // set_columns fits max_columns to the columns unless set_columns_len was called first, so set
+    // the units a page holds with set_columns_len or every column lands on one page.
+    pub fn set_horizontal_paginate(&mut self, paginate: bool) {
+        self.horizontal_paginate = paginate;
+    }
+    pub fn set_repeat_column(&mut self, column: Option<usize>) {
+        self.repeat_column = column;
+    }
+    // Columns wider than max_columns in total are scaled down together to fit the page width
+    pub fn set_shrink_to_fit_width(&mut self, shrink: bool) {
+        self.shrink_to_fit_width = shrink;
+    }
+    // Drawn in place of empty and missing cells, e.g. "\u{2014}" for sparse reports
+    pub fn set_empty_placeholder(&mut self, placeholder: Option<String>) {
+        self.empty_placeholder = placeholder;
+    }
+    // Gap in mm between neighbouring cells, each cell then gets its own box instead of sharing edges
+    pub fn set_cell_gutter(&mut self, gutter: f64) {
+        self.cell_gutter = gutter;
+    }
+    pub fn set_header_style(&mut self, style: HeaderStyle) {
+        self.header_style = style;
+    }
+    // Used for the header repeated on every page after the first, None keeps the header style
+    pub fn set_continuation_header_style(&mut self, style: Option<HeaderStyle>) {
+        self.continuation_header_style = style;
+    }
+    // Caps rows that grow to fit wrapped text, lines beyond the cap are clipped. Rows given an
+    // explicit height with set_row_heights are not capped.
+    pub fn set_max_row_height(&mut self, max_row_height: Option<f64>) {
+        self.max_row_height = max_row_height;
+    }
+    // Leaves out the repeated header on a page that starts with a section row, the section label
+    // being enough context there. The first page always has the header.
+    pub fn set_skip_header_before_section(&mut self, skip: bool) {
+        self.skip_header_before_section = skip;
+    }
+    pub fn set_position_anchor(&mut self, anchor: PositionAnchor) {
+        self.position_anchor = anchor;
+    }
+    // add_table bookmarks the first page the table has rows on with the title. printpdf keeps a flat
+    // list of one bookmark per page, so titles can't be nested under a chapter and a later bookmark
+    // on the same page replaces this one.
+    pub fn set_title(&mut self, title: Option<String>) {
+        self.title = title;
+    }
+    // Checks the table for settings that would draw a broken layout
+    pub fn validate(&self) -> Result<(), TableError> {
+        if let Some(index) = self.columns.iter().position(|c| c.width == 0) {
+            return Err(TableError::ZeroWidthColumn { index: Some(index) });
+        }
+        // shrink_to_fit_width scales wide columns down to the page, otherwise they run off it
+        match self.columns.iter().position(|c| c.width > self.max_columns) {
+            Some(index) if !self.shrink_to_fit_width => Err(TableError::ColumnTooWide { index, width: self.columns[index].width, max_columns: self.max_columns }),
+            _ => Ok(())
+        }
+    }
+    // validate, then checks the widths in mm on the page, where a column's min_width_mm can push the
+    // table past the right margin
+    pub fn validate_for(&self, page_size: &PageSize) -> Result<(), TableError> {
+        self.validate()?;
+        let right_margin = page_size.width - page_size.margin_width;
+        let overflowing = self.column_extents(page_size).into_iter().enumerate().find(|&(_, (x, width))| x + width > right_margin + 1e-9);
+        match overflowing {
+            Some((index, (x, width))) => Err(TableError::ColumnOverflowsPage { index, width_mm: width, available_mm: (right_margin - x).max(0.0) }),
+            None => Ok(())
+        }
+    }
+    // Keeps rows start to end (inclusive) on one page, moving them all to the next page when they
+    // don't fit below the previous rows. A group taller than a page breaks as usual, check for those
+    // with oversized_kept_groups.
+    pub fn keep_together(&mut self, start: usize, end: usize) {
+        if start > end {
+            panic!("Start of a group of rows cannot be after its end");
+        }
+        self.kept_groups.push((start, end));
+    }
+    // The (start, end) of every kept group too tall to fit on a page, which add_table breaks like
+    // ungrouped rows
+    pub fn oversized_kept_groups(&self, page_size: &PageSize) -> Vec<(usize, usize)> {
+        self.kept_groups.iter().filter_map(|&(start, _)| self.kept_group_starting_at(start)).filter(|&group| !self.group_fits_on_page(page_size, group)).collect()
+    }
+    fn kept_group_starting_at(&self, row_index: usize) -> Option<(usize, usize)> {
+        self.kept_groups.iter().find(|&&(start, _)| start == row_index).map(|&(start, end)| (start, end.min(self.rows.len().saturating_sub(1))))
+    }
+    fn group_height(&self, page_size: &PageSize, (start, end): (usize, usize)) -> f64 {
+        let widths = self.column_widths(page_size);
+        (start..=end).map(|r| self.height_of_row_with(r, &widths)).sum()
+    }
+    // Whether the group fits below the repeated header on an otherwise empty page
+    fn group_fits_on_page(&self, page_size: &PageSize, group: (usize, usize)) -> bool {
+        let room = self.continuation_top(page_size) - self.height_of_row(page_size, 0) - page_size.margin_height;
+        self.group_height(page_size, group) <= room
+    }
+    // Draws a line through the middle of the row, e.g. a cancelled invoice line
+    pub fn strike_row(&mut self, index: usize) {
+        self.struck_rows.insert(index);
+    }
+    // Colour for the text of struck rows, e.g. grey, None keeps it black
+    pub fn set_struck_text_color(&mut self, color: Option<Color>) {
+        self.struck_text_color = color;
+    }
+    // Underlines the header across the table, drawn over the header's borders
+    pub fn set_header_rule(&mut self, rule: Option<Rule>) {
+        self.header_rule = rule;
+    }
+    pub fn set_column_emphasis(&mut self, emphasis: Option<ColumnEmphasis>) {
+        self.column_emphasis = emphasis;
+    }
+    // Emphasises the nth, 2nth, ... column, e.g. every 4th for quarter boundaries
+    pub fn emphasize_every_nth(&mut self, n: usize, fill: Option<Color>, left_rule: Option<Rule>) {
+        let columns = (0..self.columns.len()).filter(|i| n > 0 && (i + 1) % n == 0).collect();
+        self.column_emphasis = Some(ColumnEmphasis { columns, fill, left_rule });
+    }
+    // Baselines for the rows of a fixed template, e.g. the lines of a pre-printed form, starting with
+    // the header. The table then stays on one page, rows past the end of the list follow on below
+    // the last positioned one.
+    pub fn set_row_y_positions(&mut self, positions: Vec<f64>) {
+        self.row_y_positions = positions;
+    }
+    // One line describing the table's shape for logs and bug reports, e.g.
+    // "Table: 42 rows × 4 cols, row_height 7.5mm, borders on, est. 3 pages on A4"
+    pub fn summary(&self, page_size: &PageSize) -> String {
+        let borders = match self.borders {
+            true => "on",
+            false => "off"
+        };
+        format!("Table: {} rows \u{d7} {} cols, row_height {}mm, borders {}, est. {} pages on {}", self.rows.len(), self.columns.len(), self.row_height, borders,
+            self::table_page_count(self, page_size, self.position_y), page_size.name())
+    }
+    // Marks a text cell as right to left: it is aligned to the cell's right edge and its characters
+    // are drawn in reverse order. There is no bidi reordering or shaping, so mixed-direction text and
+    // scripts with joining forms like Arabic need to be prepared beforehand, in a font that has them.
+    pub fn set_cell_rtl(&mut self, row_index: usize, column_index: usize) {
+        self.rtl_cells.insert((row_index, column_index));
+    }
+    fn is_rtl(&self, row_index: usize, column_index: usize) -> bool {
+        self.rtl_cells.contains(&(row_index, column_index)) || self.columns.get(column_index).map(|c| c.rtl).unwrap_or(false)
+    }
+    // Draws a small image at the left edge of a text cell, scaled to the row height with the text moved
+    // right of it, e.g. a coloured status dot
+    pub fn set_cell_icon(&mut self, row_index: usize, column_index: usize, icon: printpdf::Image) {
+        self.cell_icons.insert((row_index, column_index), icon.image);
+    }
+    // Width taken by a cell's icon and the gap after it, 0 without one
+    fn icon_extent(&self, row_index: usize, column_index: usize) -> f64 {
+        match self.cell_icons.get(&(row_index, column_index)) {
+            Some(icon) if icon.height.0 > 0 => icon.width.0 as f64 / icon.height.0 as f64 * self.icon_height() + ICON_GAP * self.scale,
+            _ => 0.0
+        }
+    }
+    fn icon_height(&self) -> f64 {
+        self.scaled_row_height() - self.cell_padding() * 2.0
+    }
+    fn source_column(&self, column_index: usize) -> usize {
+        self.source_columns.get(column_index).copied().unwrap_or(column_index)
+    }
+    // Applied to the text of every cell, headers included, as it is drawn. The rows are left as they are.
+    pub fn set_cell_transform<F: Fn(usize, usize, &str) -> String + 'static>(&mut self, transform: F) {
+        self.cell_transform = Some(Rc::new(transform));
+    }
+    fn display_text(&self, row_index: usize, column_index: usize, text: &str) -> String {
+        // the transform sees the column of the table it was set on, also in a page's column group
+        let text = match &self.cell_transform {
+            Some(transform) => transform(row_index, self.source_column(column_index), text),
+            None => text.to_string()
+        };
+        sanitize_text(&text, self.control_char_policy)
+    }
+    // Height of all row boxes stacked, ignoring page breaks
+    pub fn total_height(&self, page_size: &PageSize) -> f64 {
+        let widths = self.column_widths(page_size);
+        (0..self.rows.len()).map(|r| self.height_of_row_with(r, &widths)).sum()
+    }
+    // Whether the rows, laid out for page_size, fit in a width_mm by height_mm box. Wrapped rows are
+    // measured with the table's own fonts, as set by set_fonts.
+    pub fn fits_in(&self, page_size: &PageSize, width_mm: f64, height_mm: f64) -> bool {
+        self.width_mm(page_size) <= width_mm && self.total_height(page_size) <= height_mm
+    }
+    // Moves the table so its rows sit centred between the top and bottom margins. Call it once the
+    // rows are added, a table taller than the page is left starting at the top.
+    pub fn center_vertically(&mut self, page_size: &PageSize) {
+        let inner_height = page_size.height - page_size.margin_height * 2.0;
+        let top = page_size.margin_height + (inner_height + self.total_height(page_size)) / 2.0;
+        self.position_y = self.position_for_top(top.min(self.continuation_top(page_size)));
+    }
+    // Moves the table so its last row ends offset_from_bottom mm above the bottom margin, e.g. for a
+    // totals block. Call it once the rows are added.
+    pub fn anchor_bottom(&mut self, page_size: &PageSize, offset_from_bottom: f64) {
+        let top = page_size.margin_height + offset_from_bottom + self.total_height(page_size);
+        self.position_y = self.position_for_top(top);
+    }
+    // Index of the page add_table will leave the table on when starting at start_y, counted from 0
+    // for the page it starts on, so that page can be prepared before the table is drawn
+    pub fn final_layer_page(&self, page_size: &PageSize, start_y: f64) -> usize {
+        self::table_page_count(self, page_size, start_y) - 1
+    }
+    // Moves the table to the top margin, the middle of the page or onto the bottom margin. Call it
+    // once the rows are added.
+    pub fn place(&mut self, page_size: &PageSize, anchor: VerticalAnchor) {
+        match anchor {
+            VerticalAnchor::Top => self.position_y = self.position_for_top(page_size.printable_top()),
+            VerticalAnchor::Center => self.center_vertically(page_size),
+            VerticalAnchor::Bottom => self.anchor_bottom(page_size, 0.0)
+        }
+    }
+    // Every box and text position add_table would draw, for debugging layouts outside the PDF.
+    // With the serde feature the report can be serialized, e.g. to JSON.
+    pub fn layout_report(&self, page_size: &PageSize) -> LayoutReport {
+        let groups = self.column_groups();
+        let mut pages = Vec::new();
+        let mut end_y = self.position_y;
+        for (group, (mut part, layout)) in groups.iter().zip(self::measure_column_groups(self, page_size, self.position_y)) {
+            for page in layout.pages.iter() {
+                part.position_y = page.position_y;
+                let rows = page.rows.iter().map(|row| RowReport {
+                    index: row.index,
+                    top: row.top,
+                    height: row.height,
+                    cells: part.cell_reports(page_size, row, group)
+                }).collect();
+                pages.push(PageReport { page: pages.len() + 1, position_y: page.position_y, rows });
+            }
+            end_y = layout.end_y;
+        }
+        LayoutReport {
+            pages,
+            end_y,
+            font_size: self.font_size(),
+            line_height: line_height(self.font_size())
+        }
+    }
+    // Rough share of each page's area covered by ink, worked out from the layout_report placements, e.g.
+    // for print cost estimates. Filled cells and image rows count in full, text as its measured width
+    // times the font size times TEXT_INK_RATIO. Borders are left out and overlaps count twice, capped at 1.
+    pub fn coverage_estimate(&self, page_size: &PageSize) -> Vec<f64> {
+        let report = self.layout_report(page_size);
+        let text_height = report.font_size * PT_TO_MM;
+        report.pages.iter().map(|page| {
+            let header_style = match page.page {
+                1 => &self.header_style,
+                _ => self.continuation_header_style.as_ref().unwrap_or(&self.header_style)
+            };
+            let mut covered = 0.0;
+            for row in page.rows.iter() {
+                for cell in row.cells.iter() {
+                    let filled = match self.section_rows.get(&row.index) {
+                        Some(section) => section.fill.is_some(),
+                        None if row.index == 0 => header_style.fill.is_some(),
+                        None => self.image_rows.contains_key(&row.index)
+                            || self.summary_style(row.index).map(|style| style.fill.is_some())
+                                .unwrap_or_else(|| self.column_emphasis.as_ref().is_some_and(|emphasis| emphasis.fill.is_some() && emphasis.columns.contains(&cell.column)))
+                    };
+                    if filled {
+                        covered += cell.width * cell.height;
+                    }
+                    let font = match cell.bold {
+                        true => &self.bold_font,
+                        false => &self.regular_font
+                    };
+                    covered += cell.lines.iter().map(|line| text_width(line, font, report.font_size) * text_height * TEXT_INK_RATIO).sum::<f64>();
+                }
+            }
+            (covered / (page_size.width * page_size.height)).min(1.0)
+        }).collect()
+    }
+    fn cell_reports(&self, page_size: &PageSize, row: &PlacedRow, columns: &[usize]) -> Vec<CellReport> {
+        let baseline = self.row_baseline(row.top, row.index);
+        if self.section_rows.contains_key(&row.index) {
+            let (x, width) = self.extent(page_size);
+            let (x, y, width, height) = self.inset_box(x, row.top - row.height, width, row.height);
+            let lines = self.cell_lines(row.index, 0, width);
+            let label_width = lines.first().map(|label| text_width(label, &self.bold_font, self.font_size())).unwrap_or(0.0);
+            let text_x = x + (width - label_width) / 2.0;
+            return vec![CellReport { column: 0, x, y, width, height, text_x, text_y: baseline, lines, bold: true }];
+        }
+        if self.image_rows.contains_key(&row.index) {
+            let (x, width) = self.extent(page_size);
+            let y = row.top - row.height;
+            return vec![CellReport { column: 0, x, y, width, height: row.height, text_x: x, text_y: y, lines: Vec::new(), bold: false }];
+        }
+        let cells = match self.empty_placeholder {
+            Some(_) => self.columns.len(),
+            None => self.rows[row.index].len().min(self.columns.len())
+        };
+        let extents = self.column_extents(page_size);
+        (0..cells).map(|c_index| {
+            let (x, width) = extents[c_index];
+            let (x, y, width, height) = self.inset_box(x, row.top - row.height, width, row.height);
+            let lines = self.cell_lines(row.index, c_index, extents[c_index].1);
+            CellReport { column: columns[c_index], x, y, width, height, text_x: x + self.icon_extent(row.index, c_index) + self.text_indent(), text_y: baseline, lines, bold: self.is_bold_row(row.index) }
+        }).collect()
+    }
+    // The text of a cell line by line as it is drawn, empty for images and blank cells
+    fn cell_lines(&self, row_index: usize, column_index: usize, column_width: f64) -> Vec<String> {
+        if let Some(lines) = self.wrapped_lines(row_index, column_index, column_width) {
+            return lines;
+        }
+        match self.rows[row_index].get(column_index) {
+            Some(CellContent::Text(text)) if !text.trim().is_empty() => vec![self.display_text(row_index, column_index, text)],
+            Some(CellContent::Runs(runs)) => vec![runs.iter().map(|run| self.display_text(row_index, column_index, &run.text)).collect()],
+            Some(CellContent::Image(_)) | Some(CellContent::Sparkline(_)) => Vec::new(),
+            _ => self.empty_placeholder.iter().map(|placeholder| self.display_text(row_index, column_index, placeholder)).collect()
+        }
+    }
+    // Returns the column indices drawn together on each page. Without horizontal pagination, or
+    // when the columns fit, that is one group with every column.
+    pub fn column_groups(&self) -> Vec<Vec<usize>> {
+        let all: Vec<usize> = (0..self.columns.len()).collect();
+        let total: usize = self.columns.iter().map(|c| c.width).sum();
+        if !self.horizontal_paginate || total <= self.max_columns {
+            return vec![all];
+        }
+        // a label column that leaves no room for anything else is not repeated
+        let repeat = self.repeat_column.filter(|&i| i < self.columns.len() && self.columns[i].width < self.max_columns);
+        let start: Vec<usize> = repeat.into_iter().collect();
+        let start_width: usize = start.iter().map(|&i| self.columns[i].width).sum();
+
+        let mut groups = Vec::new();
+        let mut group = start.clone();
+        let mut width = start_width;
+        for index in all.into_iter().filter(|&i| Some(i) != repeat) {
+            let column_width = self.columns[index].width;
+            if group.len() > start.len() && width + column_width > self.max_columns {
+                groups.push(group);
+                group = start.clone();
+                width = start_width;
+            }
+            group.push(index);
+            width += column_width;
+        }
+        if group.len() > start.len() {
+            groups.push(group);
+        }
+        groups
+    }
+    // Returns a copy of the table with only the given columns, in the given order
+    pub fn select_columns(&self, indices: &[usize]) -> Table {
+        if let Some(index) = indices.iter().find(|&&i| i >= self.columns.len()) {
+            panic!("Column Index {} cannot be larger or equal than the number of columns", index);
+        }
+        let mut table = self.clone();
+        table.columns = indices.iter().map(|&i| self.columns[i].clone()).collect();
+        table.merge_vertical_columns = (0..indices.len()).filter(|&n| self.merge_vertical_columns.contains(&indices[n])).collect();
+        table.repeat_column = self.repeat_column.and_then(|column| indices.iter().position(|&i| i == column));
+        table.source_columns = indices.iter().map(|&i| self.source_column(i)).collect();
+        if let (Some(emphasis), Some(selected)) = (&self.column_emphasis, table.column_emphasis.as_mut()) {
+            selected.columns = (0..indices.len()).filter(|&n| emphasis.columns.contains(&indices[n])).collect();
+        }
+        table.rtl_cells = self.rtl_cells.iter().flat_map(|&(r_index, c_index)| {
+            (0..indices.len()).filter(move |&n| indices[n] == c_index).map(move |n| (r_index, n))
+        }).collect();
+        table.cell_icons = self.cell_icons.iter().flat_map(|(&(r_index, c_index), icon)| {
+            (0..indices.len()).filter(move |&n| indices[n] == c_index).map(move |n| ((r_index, n), icon.clone()))
+        }).collect();
+        table.rows = self.rows.iter().enumerate().map(|(r_index, row)| {
+            match self.is_full_width(r_index) {
+                true => row.clone(),
+                false => indices.iter().filter_map(|&i| row.get(i).cloned()).collect()
+            }
+        }).collect();
+        table
+    }
+    // Returns the (row, column) of every text cell wider than its column at the given font size
+    pub fn would_overflow(&self, page_size: &PageSize, font: &IndirectFontRef, font_size: f64) -> Vec<(usize, usize)> {
+        let mut overflowing = Vec::new();
+        let extents = self.column_extents(page_size);
+        for (r_index, row) in self.rows.iter().enumerate() {
+            for (c_index, cell) in row.iter().enumerate().take(self.columns.len()) {
+                // measured as draw_cell draws it, after the transform and with the icon and prefix
+                let size = font_size * self.scale;
+                let content_width = match cell {
+                    CellContent::Text(text) => {
+                        let text = self.display_text(r_index, c_index, text);
+                        match self.columns[c_index].currency_prefix.as_deref() {
+                            Some(prefix) if r_index != 0 => {
+                                let value = text.strip_prefix(prefix).unwrap_or(&text).trim();
+                                text_width(prefix, font, size) + text_width(value, font, size)
+                            }
+                            _ => text_width(&text, font, size)
+                        }
+                    }
+                    CellContent::Runs(runs) => runs.iter().map(|run| text_width(&self.display_text(r_index, c_index, &run.text), self.run_font(run, font), run.size_and_rise(size, self.scale).0)).sum(),
+                    CellContent::Image(_) | CellContent::Sparkline(_) => continue
+                };
+                let (_, width) = extents[c_index];
+                if self.icon_extent(r_index, c_index) + self.text_indent() * 2.0 + content_width > width {
+                    overflowing.push((r_index, c_index));
+                }
+            }
+        }
+        overflowing
+    }
+    fn run_font<'a>(&'a self, run: &TextRun, font: &'a IndirectFontRef) -> &'a IndirectFontRef {
+        match run.bold {
+            true => &self.bold_font,
+            false => font
+        }
+    }
+    // Total width of the columns in mm, the horizontal counterpart of total_height
+    pub fn width_mm(&self, page_size: &PageSize) -> f64 {
+        self.column_widths(page_size).iter().sum::<f64>() + self.spacing_total()
+    }
+    fn spacing_total(&self) -> f64 {
+        self.column_spacing * self.columns.len().saturating_sub(1) as f64
+    }
+    // Returns the left edge and total width of the table
+    fn extent(&self, page_size: &PageSize) -> (f64, f64) {
+        (self.left_edge(page_size), self.width_mm(page_size))
+    }
+    fn left_edge(&self, page_size: &PageSize) -> f64 {
+        // a table wider than the margins allow starts at the left margin whatever the alignment
+        let spare = (page_size.width - page_size.margin_width * 2.0 - self.width_mm(page_size)).max(0.0);
+        match self.horizontal_align {
+            Alignment::Left => page_size.margin_width,
+            Alignment::Center => page_size.margin_width + spare / 2.0,
+            Alignment::Right => page_size.margin_width + spare
+        }
+    }
+    // Left edge of every column in mm, in one pass over the widths
+    pub fn column_x_positions(&self, page_size: &PageSize) -> Vec<f64> {
+        let mut x = self.left_edge(page_size);
+        self.column_widths(page_size).iter().map(|width| {
+            let left = x;
+            x += width + self.column_spacing;
+            left
+        }).collect()
+    }
+    // (left edge, width) of every column
+    fn column_extents(&self, page_size: &PageSize) -> Vec<(f64, f64)> {
+        self.column_x_positions(page_size).into_iter().zip(self.column_widths(page_size)).collect()
+    }
+    // Widths of the columns in mm. Columns that would be narrower than their minimum are widened to
+    // it and the others shrink in proportion, so the table keeps its width where it can.
+    fn column_widths(&self, page_size: &PageSize) -> Vec<f64> {
+        let column_size = self.column_size(page_size);
+        let natural: Vec<f64> = self.columns.iter().map(|c| c.width as f64 * column_size).collect();
+        // minimums scale with the table like the natural widths
+        let minimums: Vec<f64> = self.columns.iter().map(|c| c.min_width_mm.unwrap_or(MIN_COLUMN_WIDTH_MM) * self.scale).collect();
+        // the spacing between columns comes out of their widths
+        let total: f64 = (natural.iter().sum::<f64>() - self.spacing_total()).max(0.0);
+        let mut pinned = vec![false; natural.len()];
+        loop {
+            let pinned_width: f64 = (0..natural.len()).filter(|&i| pinned[i]).map(|i| minimums[i]).sum();
+            let free_width: f64 = (0..natural.len()).filter(|&i| !pinned[i]).map(|i| natural[i]).sum();
+            let factor = match free_width > 0.0 {
+                true => (total - pinned_width) / free_width,
+                false => 1.0
+            };
+            let widths: Vec<f64> = (0..natural.len()).map(|i| match pinned[i] {
+                true => minimums[i],
+                false => natural[i] * factor
+            }).collect();
+            let too_narrow: Vec<usize> = (0..natural.len()).filter(|&i| !pinned[i] && widths[i] < minimums[i]).collect();
+            if too_narrow.is_empty() {
+                return widths;
+            }
+            for i in too_narrow {
+                pinned[i] = true;
+            }
+        }
+    }
+    fn column_size(&self, page_size: &PageSize) -> f64 {
+        let inner_width = page_size.width - (page_size.margin_width * 2.0);
+        let units = match self.shrink_to_fit_width {
+            true => self.max_columns.max(self.columns.iter().map(|c| c.width).sum()),
+            false => self.max_columns
+        };
+        inner_width / (units as f64) * self.scale
+    }
+    fn scaled_row_height(&self) -> f64 {
+        self.row_height * self.scale
+    }
+    fn cell_padding(&self) -> f64 {
+        match self.borders {
+            true => self.scale,
+            false => 0.0
+        }
+    }
+    fn height_of_row(&self, page_size: &PageSize, index: usize) -> f64 {
+        self.height_of_row_with(index, &self.column_widths(page_size))
+    }
+    // height_of_row with the column widths worked out by the caller, who can reuse them across rows
+    fn height_of_row_with(&self, index: usize, widths: &[f64]) -> f64 {
+        if let Some(image_row) = self.image_rows.get(&index) {
+            return image_row.height * self.scale;
+        }
+        let extra_lines = widths.iter().enumerate().filter_map(|(c, &width)| self.wrapped_lines(index, c, width)).map(|lines| lines.len()).max().unwrap_or(1) - 1;
+        let wrapped_height = self.scaled_row_height() + extra_lines as f64 * line_height(self.font_size()) + self.baseline_drop(index);
+        let grown = match self.row_heights.get(index).cloned().flatten() {
+            Some(height) => return height * self.scale,
+            None if index == 0 => wrapped_height.max(self.rotated_header_height()),
+            None => wrapped_height
+        };
+        match self.max_row_height {
+            Some(cap) => grown.min((cap * self.scale).max(self.scaled_row_height())),
+            None => grown
+        }
+    }
+    // Lines of a text cell in a column with max_lines, None for cells drawn on a single line
+    fn wrapped_lines(&self, row_index: usize, column_index: usize, column_width: f64) -> Option<Vec<String>> {
+        let column = self.columns.get(column_index)?;
+        let max_lines = column.max_lines?;
+        if (row_index == 0 && column.header_rotation != 0.0) || self.section_rows.contains_key(&row_index) {
+            return None;
+        }
+        let font = match self.is_bold_row(row_index) {
+            true => &self.bold_font,
+            false => &self.regular_font
+        };
+        match self.rows.get(row_index)?.get(column_index)? {
+            CellContent::Text(text) if !text.trim().is_empty() => {
+                let text = self.display_text(row_index, column_index, text);
+                let width = column_width - self.icon_extent(row_index, column_index) - self.text_indent() * 2.0;
+                Some(wrap_text_lines_with(&text, font, self.font_size(), width, max_lines, &self.ellipsis))
+            }
+            _ => None
+        }
+    }
+    // Height the header row needs so rotated header text stays inside it
+    fn rotated_header_height(&self) -> f64 {
+        let header = match self.rows.first() {
+            Some(header) => header,
+            None => return 0.0
+        };
+        let text_height = self.font_size() * PT_TO_MM;
+        self.columns.iter().zip(header.iter()).filter(|(column, _)| column.header_rotation != 0.0).map(|(column, cell)| {
+            let angle = column.header_rotation.to_radians();
+            let width = match cell {
+                CellContent::Text(text) => text_width(text, &self.bold_font, self.font_size()),
+                _ => 0.0
+            };
+            width * angle.sin().abs() + text_height * angle.cos().abs() + self.cell_padding() * 2.0
+        }).fold(0.0, f64::max)
+    }
+    fn first_row_top(&self, position_y: f64) -> f64 {
+        match self.position_anchor {
+            PositionAnchor::HalfRowAbove => position_y - self.scaled_row_height() * 0.5,
+            PositionAnchor::HeaderTop => position_y,
+            PositionAnchor::FirstBaseline => position_y + self.scaled_row_height() * 0.5 + self.cell_padding()
+        }
+    }
+    // Inverse of first_row_top, the anchors only shift position_y by a constant
+    fn position_for_top(&self, top: f64) -> f64 {
+        top - self.first_row_top(0.0)
+    }
+    // Top of the header on pages the table continues onto, never above the top margin
+    fn continuation_top(&self, page_size: &PageSize) -> f64 {
+        self.first_row_top(page_size.printable_top()).min(page_size.printable_top())
+    }
+    fn baseline(&self, top: f64) -> f64 {
+        top - self.scaled_row_height() * 0.5 - self.cell_padding()
+    }
+    // The baseline every cell of the row shares, lowered for runs larger than the table's font
+    fn row_baseline(&self, top: f64, row_index: usize) -> f64 {
+        self.baseline(top) - self.baseline_drop(row_index)
+    }
+    // How much further below the top the largest run's ascent needs the baseline to be
+    fn baseline_drop(&self, row_index: usize) -> f64 {
+        let largest = self.rows.get(row_index).into_iter().flatten().filter_map(|cell| match cell {
+            CellContent::Runs(runs) => runs.iter().map(|run| run.size_and_rise(self.font_size(), self.scale).0).reduce(f64::max),
+            _ => None
+        }).fold(self.font_size(), f64::max);
+        (largest - self.font_size()) * PT_TO_MM * ASCENT
+    }
+    // Inverse of baseline
+    fn top_for_baseline(&self, baseline: f64) -> f64 {
+        baseline + self.scaled_row_height() * 0.5 + self.cell_padding()
+    }
+    fn text_indent(&self) -> f64 {
+        match self.borders {
+            true => self.scaled_row_height() * 0.25,
+            false => 0.0
+        }
+    }
+    // Shrinks a cell's box by half the gutter on every side
+    fn inset_box(&self, x: f64, y: f64, width: f64, height: f64) -> (f64, f64, f64, f64) {
+        let inset = (self.cell_gutter / 2.0).max(0.0);
+        (x + inset, y + inset, width - inset * 2.0, height - inset * 2.0)
+    }
+    fn font_size(&self) -> f64 {
+        DEFAULT_FONT_SIZE * self.scale
+    }
 }
 
+// Numbers table captions across a document and keeps them for a list of tables
+pub struct TableCounter {
+    pub captions: Vec<String>
+}
+
+impl TableCounter {
+    pub fn new() -> TableCounter {
+        TableCounter {
+            captions: Vec::new()
+        }
+    }
+    pub fn count(&self) -> usize {
+        self.captions.len()
+    }
+    pub fn next_caption(&mut self, caption: &str) -> String {
+        let numbered = format!("Table {}: {}", self.captions.len() + 1, caption);
+        self.captions.push(numbered.clone());
+        numbered
+    }
+    pub fn number_table(&mut self, table: &mut Table, caption: &str) {
+        let numbered = self.next_caption(caption);
+        table.set_caption(numbered);
+    }
+}
+
+impl Default for TableCounter {
+    fn default() -> TableCounter {
+        TableCounter::new()
+    }
+}
+
+#[derive(Clone)]
 pub struct Column {
-    pub width: usize
+    pub width: usize,
+    // Counter-clockwise rotation of the header text in degrees
+    pub header_rotation: f64,
+    // Text cells wrap to at most this many lines, rows grow to fit them
+    pub max_lines: Option<usize>,
+    // Overrides MIN_COLUMN_WIDTH_MM for this column, scaled with the table like it
+    pub min_width_mm: Option<f64>,
+    // Text in this column reads right to left, see Table::set_cell_rtl
+    pub rtl: bool,
+    // Drawn at the left edge of body cells with the value right-aligned, e.g. "$". A value already
+    // starting with the prefix is drawn without it.
+    pub currency_prefix: Option<String>
 }
 
 impl Column {
-    #[allow(dead_code)]
-    pub fn default() -> Column {
+    // Columns must be at least 1 unit wide, a zero-width column throws off the widths of the others
+    pub fn new(width: usize) -> Result<Column, TableError> {
+        match width {
+            0 => Err(TableError::ZeroWidthColumn { index: None }),
+            _ => Ok(Column { width, ..Column::default() })
+        }
+    }
+}
+
+impl Default for Column {
+    fn default() -> Column {
         Column {
-            width: 1
+            width: 1,
+            header_rotation: 0.0,
+            max_lines: None,
+            min_width_mm: None,
+            rtl: false,
+            currency_prefix: None
         }
     }
 }
@@ -115,16 +1294,31 @@ pub fn calculate_column_coordinates(page_size: &PageSize, column_index: usize, c
     }
 
     let inner_width = page_size.width - (page_size.margin_width * 2.0);
-    let inner_height = page_size.height - (page_size.margin_height * 2.0);
-
     let column_size = inner_width / (columns as f64);
     let x = page_size.margin_width + (column_size * column_index as f64);
-    let y = inner_height.min(y);
+    let y = page_size.printable_top().min(y);
 
     (x, y)
 }
 
-pub fn calculate_border_points(page_size: &PageSize, table: &Table, column_index: usize, row_num: usize) -> Vec<(Point, bool)> {
+// Row positions assume every row before row_num is on the same page, as on the first page. widths
+// are the column widths, measured once by the caller.
+fn row_top(page_size: &PageSize, table: &Table, row_num: usize, widths: &[f64]) -> f64 {
+    let top = table.first_row_top(table.position_y);
+    match (row_num, table.row_y_positions.len()) {
+        // rows at fixed baselines are placed like measure_fixed_rows, from the last positioned row
+        (_, positioned) if positioned > 0 => {
+            let anchor = row_num.min(positioned - 1);
+            let top = table.top_for_baseline(table.row_y_positions[anchor]) + table.baseline_drop(anchor);
+            top - (anchor..row_num).map(|r| table.height_of_row_with(r, widths)).sum::<f64>()
+        }
+        (0, _) => table.header_top(page_size, top),
+        _ => top - (0..row_num).map(|r| table.height_of_row_with(r, widths)).sum::<f64>()
+    }
+}
+
+// Returns the (x, y, width, height) of a cell's box, with y being the bottom edge
+pub fn calculate_cell_bounds(page_size: &PageSize, table: &Table, column_index: usize, row_num: usize) -> (f64, f64, f64, f64) {
     if row_num >= table.rows.len() {
         panic!("Row index cannot be larger or equal to the number of rows in the table");
     }
@@ -132,21 +1326,26 @@ pub fn calculate_border_points(page_size: &PageSize, table: &Table, column_index
         panic!("Column Index cannot be larger or equal than the number of columns");
     }
 
-    let border_padding = table.row_height * 0.5;
-    let inner_width = page_size.width - (page_size.margin_width * 2.0);
-    let column_size = inner_width / (table.max_columns as f64);
-    let y: f64 = table.position_y - border_padding - (row_num as f64 * table.row_height);
-    let x = page_size.margin_width + table.columns.iter().take(column_index).map(|w| (w.width as f64) * column_size).sum::<f64>();
-    let right_x = page_size.margin_width + table.columns.iter().take(column_index + 1).map(|w| (w.width as f64) * column_size).sum::<f64>();
+    let extents = table.column_extents(page_size);
+    let widths: Vec<f64> = extents.iter().map(|&(_, width)| width).collect();
+    let (x, width) = extents[column_index];
+    let height = table.height_of_row_with(row_num, &widths);
+    (x, self::row_top(page_size, table, row_num, &widths) - height, width, height)
+}
+
+pub fn calculate_border_points(page_size: &PageSize, table: &Table, column_index: usize, row_num: usize) -> Vec<(Point, bool)> {
+    let (x, y, width, height) = self::calculate_cell_bounds(page_size, table, column_index, row_num);
 
     vec![
+        (Point::new(Mm(x), Mm(y + height)), false),
+        (Point::new(Mm(x + width), Mm(y + height)), false),
+        (Point::new(Mm(x + width), Mm(y)), false),
         (Point::new(Mm(x), Mm(y)), false),
-        (Point::new(Mm(right_x), Mm(y)), false),
-        (Point::new(Mm(right_x), Mm(y - table.row_height)), false),
-        (Point::new(Mm(x), Mm(y - table.row_height)), false),
     ]
 }
 
+// Returns where a cell's text starts, the baseline being half a row plus the cell padding below the
+// row's top edge. See PositionAnchor for where the first row's top edge is.
 pub fn calculate_cell_coordinates(page_size: &PageSize, table: &Table, column_index: usize, row_num: usize) -> (f64, f64) {
     if row_num >= table.rows.len() {
         panic!("Row index cannot be larger or equal to the number of rows in the table");
@@ -154,96 +1353,900 @@ pub fn calculate_cell_coordinates(page_size: &PageSize, table: &Table, column_in
     if column_index >= table.columns.len() {
         panic!("Column Index cannot be larger or equal than the number of columns");
     }
-    
-    let border_padding = match table.borders {
-        true => table.row_height * 0.25,
-        false => 0.0
+
+    let extents = table.column_extents(page_size);
+    let widths: Vec<f64> = extents.iter().map(|&(_, width)| width).collect();
+    let (x, _) = extents[column_index];
+    (x + table.text_indent(), table.row_baseline(self::row_top(page_size, table, row_num, &widths), row_num))
+}
+
+// Draws a checkbox at the start of a cell, where its text would begin, ticked when checked
+pub fn add_checkbox(layer: &PdfLayerReference, page_size: &PageSize, table: &Table, column_index: usize, row_num: usize, checked: bool) {
+    let (x, y, _, height) = self::calculate_cell_bounds(page_size, table, column_index, row_num);
+    let size = (height - table.cell_padding() * 2.0).min(height * 0.6);
+    let left = x + table.text_indent().max(table.cell_padding());
+    let bottom = y + (height - size) / 2.0;
+    layer.add_shape(self::rect_shape(table, left, bottom, size, size, false, true));
+
+    if checked {
+        let point = |dx: f64, dy: f64| (Point::new(Mm(left + size * dx), Mm(bottom + size * dy)), false);
+        layer.add_shape(Line {
+            points: vec![point(0.2, 0.5), point(0.4, 0.25), point(0.8, 0.8)],
+            is_closed: false,
+            has_fill: false,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
+    }
+}
+
+// Rounds a mm coordinate to the nearest multiple of the grid size, e.g. 25.4 / dpi for device pixels
+pub fn snap_to_grid(value: f64, grid: Option<f64>) -> f64 {
+    match grid {
+        Some(size) if size > 0.0 => (value / size).round() * size,
+        _ => value
+    }
+}
+
+fn rect_shape(table: &Table, x: f64, y: f64, width: f64, height: f64, has_fill: bool, has_stroke: bool) -> Line {
+    let left = self::snap_to_grid(x, table.snap_to);
+    let right = self::snap_to_grid(x + width, table.snap_to);
+    let bottom = self::snap_to_grid(y, table.snap_to);
+    let top = self::snap_to_grid(y + height, table.snap_to);
+    Line {
+        points: vec![
+            (Point::new(Mm(left), Mm(top)), false),
+            (Point::new(Mm(right), Mm(top)), false),
+            (Point::new(Mm(right), Mm(bottom)), false),
+            (Point::new(Mm(left), Mm(bottom)), false),
+        ],
+        is_closed: true,
+        has_fill,
+        has_stroke,
+        is_clipping_path: false,
+    }
+}
+
+// Draws the edges of a cell box the border style asks for
+fn draw_cell_border(layer: &PdfLayerReference, table: &Table, row_index: usize, (x, y, width, height): (f64, f64, f64, f64)) {
+    if !table.borders {
+        return;
+    }
+    let vertical = !(row_index == 0 && table.dividers_below_header);
+    match (table.border_style, vertical) {
+        (BorderStyle::Grid, true) => layer.add_shape(self::rect_shape(table, x, y, width, height, false, true)),
+        (BorderStyle::Grid, false) => {
+            layer.add_shape(self::line_shape(table, x, y + height, x + width, y + height));
+            layer.add_shape(self::line_shape(table, x, y, x + width, y));
+        }
+        (BorderStyle::VerticalOnly, true) => {
+            layer.add_shape(self::line_shape(table, x, y, x, y + height));
+            layer.add_shape(self::line_shape(table, x + width, y, x + width, y + height));
+        }
+        (BorderStyle::VerticalOnly, false) => ()
+    }
+}
+
+fn draw_section_row(layer: &PdfLayerReference, page_size: &PageSize, table: &Table, row: &PlacedRow, section: &SectionRow, font: &IndirectFontRef) {
+    let (x, width) = table.extent(page_size);
+    let (x, y, width, height) = table.inset_box(x, row.top - row.height, width, row.height);
+    if let Some(fill) = &section.fill {
+        layer.set_fill_color(fill.clone());
+        layer.add_shape(self::rect_shape(table, x, y, width, height, true, false));
+        layer.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+    }
+    self::draw_cell_border(layer, table, row.index, (x, y, width, height));
+    if let Some(CellContent::Text(label)) = table.rows[row.index].first() {
+        let label = table.display_text(row.index, 0, label);
+        let label_x = x + (width - text_width(&label, font, table.font_size())) / 2.0;
+        use_text_with_fallback(layer, &label, table.font_size(), label_x, table.baseline(row.top), font, table.fallback_font.as_ref());
+    }
+}
+
+fn draw_cell(layer: &PdfLayerReference, table: &Table, column_index: usize, (x, width): (f64, f64), row: &PlacedRow, font: &IndirectFontRef) {
+    if table.is_merged_cell(row.index, column_index) {
+        return;
+    }
+    let column_width = width;
+    let (x, y, width, height) = table.inset_box(x, row.top - row.height, width, row.height);
+    if !(table.borders_only_filled && table.is_blank_cell(row.index, column_index)) {
+        self::draw_cell_border(layer, table, row.index, (x, y, width, height));
+    }
+    let rotation = table.columns[column_index].header_rotation;
+    let placeholder = table.empty_placeholder.clone().map(CellContent::Text);
+    let content = match (table.rows[row.index].get(column_index), &placeholder) {
+        (Some(CellContent::Text(text)), Some(placeholder)) if text.trim().is_empty() => placeholder,
+        (Some(content), _) => content,
+        (None, Some(placeholder)) => placeholder,
+        (None, None) => return
     };
-    let cell_padding = match table.borders {
-        true => 1.0,
-        false => 0.0
+    let rtl = table.is_rtl(row.index, column_index);
+    let (x, width) = match table.cell_icons.get(&(row.index, column_index)) {
+        Some(icon) if !matches!(content, CellContent::Image(_) | CellContent::Sparkline(_)) => {
+            let icon = printpdf::Image::from(icon.clone());
+            let (_, icon_height) = self::image_size_mm(&icon, IMAGE_DPI);
+            if icon_height > 0.0 {
+                let scale = table.icon_height() / icon_height;
+                let bottom = row.top - table.cell_padding() - table.icon_height();
+                icon.add_to_layer(layer.clone(), Some(Mm(x + table.text_indent())), Some(Mm(bottom)), None, Some(scale), Some(scale), Some(IMAGE_DPI));
+            }
+            let offset = table.icon_extent(row.index, column_index);
+            (x + offset, width - offset)
+        }
+        _ => (x, width)
     };
+    match content {
+        CellContent::Text(text) if row.index == 0 && rotation != 0.0 => {
+            let offset = table.font_size() * PT_TO_MM * rotation.to_radians().sin().max(0.0);
+            use_rotated_text(layer, &table.display_text(row.index, column_index, text), table.font_size(), x + table.text_indent() + offset, y + table.cell_padding(), rotation, font);
+        }
+        CellContent::Text(text) if row.index != 0 && table.columns[column_index].currency_prefix.is_some() => {
+            let prefix = table.columns[column_index].currency_prefix.as_deref().unwrap_or_default();
+            let text = table.display_text(row.index, column_index, text);
+            let value = text.strip_prefix(prefix).unwrap_or(&text).trim();
+            // blank cells get no prefix either
+            if !value.is_empty() {
+                let baseline = table.row_baseline(row.top, row.index);
+                let fallback = table.fallback_font.as_ref();
+                use_text_with_fallback(layer, prefix, table.font_size(), x + table.text_indent(), baseline, font, fallback);
+                let value_x = x + width - table.text_indent() - text_width(value, font, table.font_size());
+                use_text_with_fallback(layer, value, table.font_size(), value_x, baseline, font, fallback);
+            }
+        }
+        CellContent::Text(text) => match table.wrapped_lines(row.index, column_index, column_width) {
+            Some(lines) => {
+                // with a capped row height, or a row cut to the page, the lines may not all fit, so clip
+                // them to the cell
+                let last_baseline = table.row_baseline(row.top, row.index) - lines.len().saturating_sub(1) as f64 * line_height(table.font_size());
+                let clip = table.max_row_height.is_some() || last_baseline < row.top - row.height;
+                if clip {
+                    let mut clipping_path = self::rect_shape(table, x, y, width, height, false, false);
+                    clipping_path.set_as_clipping_path(true);
+                    layer.save_graphics_state();
+                    layer.add_shape(clipping_path);
+                }
+                let mut baseline = table.row_baseline(row.top, row.index);
+                for line in lines {
+                    self::draw_text_line(layer, table, &line, (x, width), baseline, font, rtl);
+                    baseline -= line_height(table.font_size());
+                }
+                if clip {
+                    layer.restore_graphics_state();
+                }
+            }
+            None => self::draw_text_line(layer, table, &table.display_text(row.index, column_index, text), (x, width), table.row_baseline(row.top, row.index), font, rtl)
+        },
+        CellContent::Runs(runs) => {
+            let mut run_x = x + table.text_indent();
+            for run in runs {
+                let run_font = table.run_font(run, font);
+                if let Some(color) = &run.color {
+                    layer.set_fill_color(color.clone());
+                }
+                let (size, rise) = run.size_and_rise(table.font_size(), table.scale);
+                let text = table.display_text(row.index, column_index, &run.text);
+                use_text_with_fallback(layer, &text, size, run_x, table.row_baseline(row.top, row.index) + rise, run_font, table.fallback_font.as_ref());
+                if run.color.is_some() {
+                    layer.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+                }
+                run_x += text_width(&text, run_font, size);
+            }
+        }
+        CellContent::Image(image) => {
+            let padding = table.cell_padding();
+            let (image_width, image_height) = self::image_size_mm(image, IMAGE_DPI);
+            let scale = ((width - padding * 2.0) / image_width).min((height - padding * 2.0) / image_height);
+            let offset_y = (height - image_height * scale) / 2.0;
+            printpdf::Image::from(image.image.clone()).add_to_layer(layer.clone(), Some(Mm(x + padding)), Some(Mm(y + offset_y)), None, Some(scale), Some(scale), Some(IMAGE_DPI));
+        }
+        CellContent::Sparkline(values) => self::draw_sparkline(layer, table, values, (x, y, width, height))
+    }
+}
 
-    let inner_width = page_size.width - (page_size.margin_width * 2.0);
-    let column_size = inner_width / (table.max_columns as f64);
-    let y: f64 = table.position_y - ((row_num + 1) as f64 * table.row_height) - cell_padding;
-    let x = page_size.margin_width + table.columns.iter().take(column_index).map(|w| (w.width as f64) * column_size).sum::<f64>() + border_padding;
-    (x, y)
+// Draws the values as a polyline inside the cell's padding, a flat series along the middle
+fn draw_sparkline(layer: &PdfLayerReference, table: &Table, values: &[f64], (x, y, width, height): (f64, f64, f64, f64)) {
+    if values.len() < 2 {
+        return;
+    }
+    let padding = table.cell_padding();
+    let (left, bottom, width, height) = (x + padding, y + padding, width - padding * 2.0, height - padding * 2.0);
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let step = width / (values.len() - 1) as f64;
+    let points = values.iter().enumerate().map(|(index, &value)| {
+        let share = match max > min {
+            true => (value - min) / (max - min),
+            false => 0.5
+        };
+        (Point::new(Mm(left + step * index as f64), Mm(bottom + height * share)), false)
+    }).collect();
+    layer.add_shape(Line {
+        points,
+        is_closed: false,
+        has_fill: false,
+        has_stroke: true,
+        is_clipping_path: false,
+    });
 }
 
-pub fn add_table(table: &mut Table, page_size: &PageSize, doc: &PdfDocumentReference, current_layer_ref: PdfLayerReference, y: f64, regular: &IndirectFontRef, bold: &IndirectFontRef) -> (f64, PdfLayerReference) {
+// Draws each run of the merge_vertical_columns on a page as one cell spanning its rows
+fn draw_merged_cells(layer: &PdfLayerReference, page_size: &PageSize, table: &Table, rows: &[PlacedRow], font: &IndirectFontRef) {
+    let extents = table.column_extents(page_size);
+    for &c_index in table.merge_vertical_columns.iter().filter(|&&c| c < extents.len()) {
+        for (first, last) in table.merged_runs(rows, c_index) {
+            let (first, last) = (&rows[first], &rows[last]);
+            let height = first.top - (last.top - last.height);
+            let (x, y, width, inset_height) = table.inset_box(extents[c_index].0, first.top - height, extents[c_index].1, height);
+            self::draw_cell_border(layer, table, first.index, (x, y, width, inset_height));
+            if let Some(CellContent::Text(text)) = table.rows[first.index].get(c_index) {
+                // the first row's baseline moved down by half the height the run adds to it
+                let baseline = table.baseline(first.top - (height - first.height) / 2.0);
+                let text = table.display_text(first.index, c_index, text);
+                self::draw_text_line(layer, table, &text, (x, width), baseline, font, table.is_rtl(first.index, c_index));
+            }
+        }
+    }
+}
+
+fn draw_outer_border(layer: &PdfLayerReference, page_size: &PageSize, table: &Table, rows: &[PlacedRow]) {
+    let (style, first, last) = match (table.outer_border_style, rows.first(), rows.last()) {
+        (Some(style), Some(first), Some(last)) => (style, first, last),
+        _ => return
+    };
+    if let LineStyle::Dashed { dash, gap } = style {
+        layer.set_line_dash_pattern(LineDashPattern::new(0, Some(dash), Some(gap), None, None, None, None));
+    }
+    let (x, width) = table.extent(page_size);
+    let bottom = last.top - last.height;
+    layer.add_shape(self::rect_shape(table, x, bottom, width, first.top - bottom, false, true));
+    if style != LineStyle::Solid {
+        layer.set_line_dash_pattern(LineDashPattern::default());
+    }
+}
+
+// Draws one line of cell text from the left edge, or reversed against the right edge for RTL cells
+fn draw_text_line(layer: &PdfLayerReference, table: &Table, line: &str, (x, width): (f64, f64), baseline: f64, font: &IndirectFontRef, rtl: bool) {
+    match rtl {
+        true => {
+            let reversed: String = line.chars().rev().collect();
+            let line_x = x + width - table.text_indent() - text_width(&reversed, font, table.font_size());
+            use_text_with_fallback(layer, &reversed, table.font_size(), line_x, baseline, font, table.fallback_font.as_ref());
+        }
+        false => use_text_with_fallback(layer, line, table.font_size(), x + table.text_indent(), baseline, font, table.fallback_font.as_ref())
+    }
+}
+
+// A row placed on a page, with the top edge and height of its box
+pub struct PlacedRow {
+    pub index: usize,
+    pub slot: usize,
+    pub top: f64,
+    pub height: f64
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TableError {
+    TooManyPages { pages: usize, max_pages: usize },
+    // The index is known when a whole table is validated
+    ZeroWidthColumn { index: Option<usize> },
+    ColumnTooWide { index: usize, width: usize, max_columns: usize },
+    // available_mm is the room between the column's left edge and the right margin
+    ColumnOverflowsPage { index: usize, width_mm: f64, available_mm: f64 },
+    NotEnoughLayers { pages: usize, layers: usize }
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableError::TooManyPages { pages, max_pages } => write!(f, "Table needs {} pages but may use at most {}", pages, max_pages),
+            TableError::ZeroWidthColumn { index: Some(index) } => write!(f, "Column {} has a width of 0", index),
+            TableError::ZeroWidthColumn { index: None } => write!(f, "Column width must be at least 1"),
+            TableError::ColumnTooWide { index, width, max_columns } => write!(f, "Column {} is {} units wide but the page only fits {}", index, width, max_columns),
+            TableError::ColumnOverflowsPage { index, width_mm, available_mm } => write!(f, "Column {} is {:.1}mm wide but only {:.1}mm are left before the right margin", index, width_mm, available_mm),
+            TableError::NotEnoughLayers { pages, layers } => write!(f, "Table needs {} pages but only {} layers were given", pages, layers)
+        }
+    }
+}
+
+impl std::error::Error for TableError {}
+
+// Rows placed on one page, the header being the first with slot 0 unless it was skipped before a
+// section row
+pub struct TablePage {
+    pub position_y: f64,
+    pub rows: Vec<PlacedRow>
+}
+
+pub struct TableLayout {
+    pub pages: Vec<TablePage>,
+    pub end_y: f64
+}
+
+pub fn measure_table(table: &Table, page_size: &PageSize, y: f64) -> TableLayout {
+    if !table.row_y_positions.is_empty() {
+        return self::measure_fixed_rows(table, page_size, y);
+    }
+    let layout = self::measure_rows(table, page_size, y, None);
+    let (last, previous) = match layout.pages.as_slice() {
+        [.., previous, last] => (last, previous),
+        _ => return layout
+    };
+    // orphan control: break the previous page early so the last one gets min_rows_on_last_page rows,
+    // as long as the previous page keeps at least one
+    let body_rows = |page: &TablePage| page.rows.iter().filter(|row| row.index != 0).map(|row| row.index).collect::<Vec<usize>>();
+    let last_rows = body_rows(last);
+    let previous_rows = body_rows(previous);
+    let missing = table.min_rows_on_last_page.saturating_sub(last_rows.len());
+    match (last_rows.first(), previous_rows.len()) {
+        (Some(&first), kept) if missing > 0 && kept > 1 => {
+            let moved = missing.min(kept - 1);
+            self::measure_rows(table, page_size, y, Some(first - moved))
+        }
+        _ => layout
+    }
+}
+
+// Lays the rows out top to bottom, starting a new page whenever they reach the bottom margin or at
+// the row forced_break
+fn measure_rows(table: &Table, page_size: &PageSize, y: f64, forced_break: Option<usize>) -> TableLayout {
     let mut current_y = y;
-    let mut page_num = 0;
-    let mut current_row = 0;
+    let mut pages = vec![TablePage { position_y: table.position_y, rows: Vec::new() }];
     let mut print_header = true;
-    let mut new_layer_ref = current_layer_ref.clone();
-    let headers = table.rows.get(0).unwrap();
-    
-    
+    let mut slot = 0;
+    let mut top = table.first_row_top(table.position_y);
+
+    // last row of a kept group that was found to fit on the current page
+    let mut kept_until = None;
+    let widths = table.column_widths(page_size);
+    // a row taller than a page is cut to the room below the header of a continuation page, its text
+    // clipped to the cell. Image rows are scaled to their height instead and stay as they are.
+    let room = table.continuation_top(page_size) - table.height_of_row_with(0, &widths) - page_size.margin_height;
+    let fitted_height = |r_index: usize| match table.image_rows.contains_key(&r_index) {
+        true => table.height_of_row_with(r_index, &widths),
+        false => table.height_of_row_with(r_index, &widths).min(room.max(table.scaled_row_height()))
+    };
+
     for (r_index, row) in table.rows.iter().enumerate() {
-        if current_y <= (page_size.margin_height + 7.5) {
-            page_num += 1;
-            let (new_page, new_layer) = doc.add_page(Mm(page_size.width), Mm(page_size.height), page_num.to_string());
-            new_layer_ref = doc.get_page(new_page).get_layer(new_layer);
-            current_row = r_index;
-            print_header = true;
-            table.position_y = page_size.height - page_size.margin_height;
+        let inside_kept_group = kept_until.is_some_and(|end| r_index <= end);
+        // rows already starting a continuation page stay where they are
+        let can_move = !print_header && (pages.len() == 1 || slot > 1);
+        // a row that would reach below the bottom margin moves to the next page whole
+        let row_breaks = can_move && !inside_kept_group && top - fitted_height(r_index) < page_size.margin_height;
+        let group = table.kept_group_starting_at(r_index).filter(|&group| table.group_fits_on_page(page_size, group));
+        let group_breaks = group.is_some_and(|group| can_move && top - table.group_height(page_size, group) < page_size.margin_height);
+        if let Some((_, end)) = group {
+            kept_until = Some(end);
+        }
+        let forced = forced_break == Some(r_index) && can_move;
+        if (current_y <= page_size.printable_bottom() && !inside_kept_group) || row_breaks || group_breaks || forced {
+            let position_y = page_size.printable_top();
+            pages.push(TablePage { position_y, rows: Vec::new() });
+            top = table.continuation_top(page_size);
+            print_header = !(table.skip_header_before_section && table.section_rows.contains_key(&r_index));
+            slot = 0;
         }
+        let page = pages.last_mut().unwrap();
         if print_header {
-            for (c_index, cell) in headers.iter().enumerate() {
-                if table.borders {
-                    let line1 = Line {
-                        points: self::calculate_border_points(&page_size, &table, c_index, r_index - current_row),
-                        is_closed: true,
-                        has_fill: false,
-                        has_stroke: true,
-                        is_clipping_path: false,
-                    };
-                    new_layer_ref.add_shape(line1);
-                }
-                let (x, y) = self::calculate_cell_coordinates(&page_size, &table, c_index, r_index - current_row);
-                new_layer_ref.use_text(cell,  12.0, Mm(x), Mm(y), bold);
-                current_y = y;
+            let height = table.height_of_row_with(0, &widths);
+            let header_top = table.header_top(page_size, top);
+            page.rows.push(PlacedRow { index: 0, slot: 0, top: header_top, height });
+            if !table.rows[0].is_empty() {
+                current_y = table.row_baseline(header_top, 0);
             }
+            top -= height;
             print_header = false;
+            slot = 1;
             if r_index == 0 {
                 continue;
             }
         }
-        for (c_index, cell) in row.iter().enumerate() {
-            if table.borders {
-                let line1 = Line {
-                    points: self::calculate_border_points(&page_size, &table, c_index, r_index + cmp::min(page_num, 1) - current_row),
-                    is_closed: true,
-                    has_fill: false,
-                    has_stroke: true,
-                    is_clipping_path: false,
-                };
-                new_layer_ref.add_shape(line1);
+        let height = fitted_height(r_index);
+        page.rows.push(PlacedRow { index: r_index, slot, top, height });
+        if table.image_rows.contains_key(&r_index) {
+            current_y = top - height;
+        } else if !row.is_empty() {
+            current_y = table.row_baseline(top, r_index);
+        }
+        top -= height;
+        slot += 1;
+    }
+    TableLayout {
+        pages,
+        end_y: current_y
+    }
+}
+
+// Places rows at the baselines in row_y_positions on a single page
+fn measure_fixed_rows(table: &Table, page_size: &PageSize, y: f64) -> TableLayout {
+    let mut rows = Vec::new();
+    let mut top = table.first_row_top(table.position_y);
+    let mut end_y = y;
+    let widths = table.column_widths(page_size);
+    for r_index in 0..table.rows.len() {
+        if let Some(&baseline) = table.row_y_positions.get(r_index) {
+            top = table.top_for_baseline(baseline) + table.baseline_drop(r_index);
+        }
+        let height = table.height_of_row_with(r_index, &widths);
+        rows.push(PlacedRow { index: r_index, slot: r_index, top, height });
+        end_y = table.row_baseline(top, r_index);
+        top -= height;
+    }
+    TableLayout {
+        pages: vec![TablePage { position_y: table.position_y, rows }],
+        end_y
+    }
+}
+
+// Placements from Table::layout_report, in mm from the bottom-left of the page like the rest of
+// the layout. Pages are numbered from 1 and cells carry the column index of the original table.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayoutReport {
+    pub pages: Vec<PageReport>,
+    pub end_y: f64,
+    pub font_size: f64,
+    pub line_height: f64
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageReport {
+    pub page: usize,
+    pub position_y: f64,
+    pub rows: Vec<RowReport>
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowReport {
+    pub index: usize,
+    pub top: f64,
+    pub height: f64,
+    pub cells: Vec<CellReport>
+}
+
+// The cell's box, y being its bottom edge, and its text with where the first line starts
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellReport {
+    pub column: usize,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub text_x: f64,
+    pub text_y: f64,
+    pub lines: Vec<String>,
+    pub bold: bool
+}
+
+// Measures the table and fails if it would need more than max_pages pages, e.g. to switch to a
+// condensed layout before anything is drawn
+pub fn measure_table_with_budget(table: &Table, page_size: &PageSize, y: f64, max_pages: usize) -> Result<TableLayout, TableError> {
+    // pages are counted over every column group like table_page_count, the layout returned is the
+    // first group's
+    let mut groups = self::measure_column_groups(table, page_size, y);
+    match groups.iter().map(|(_, layout)| layout.pages.len()).sum() {
+        pages if pages > max_pages => Err(TableError::TooManyPages { pages, max_pages }),
+        _ => Ok(groups.swap_remove(0).1)
+    }
+}
+
+fn draw_row(layer: &PdfLayerReference, page_size: &PageSize, table: &Table, row: &PlacedRow, header_style: &HeaderStyle, regular: &IndirectFontRef, bold: &IndirectFontRef) {
+    if let Some(section) = table.section_rows.get(&row.index) {
+        self::draw_section_row(layer, page_size, table, row, section, bold);
+        return;
+    }
+    if let (true, Some(CellContent::Image(image))) = (table.image_rows.contains_key(&row.index), table.rows[row.index].first()) {
+        let (x, width) = table.extent(page_size);
+        let (image_width, image_height) = self::image_size_mm(image, IMAGE_DPI);
+        if image_width > 0.0 && image_height > 0.0 {
+            printpdf::Image::from(image.image.clone()).add_to_layer(layer.clone(), Some(Mm(x)), Some(Mm(row.top - row.height)), None, Some(width / image_width), Some(row.height / image_height), Some(IMAGE_DPI));
+        }
+        return;
+    }
+    let cells = match table.empty_placeholder {
+        Some(_) => table.columns.len(),
+        None => table.rows[row.index].len()
+    };
+    let extents = table.column_extents(page_size);
+    let summary_style = table.summary_style(row.index);
+    if row.index != 0 && summary_style.is_none() {
+        if let Some(ColumnEmphasis { columns, fill: Some(fill), .. }) = &table.column_emphasis {
+            layer.set_fill_color(fill.clone());
+            for &c_index in columns.iter().filter(|&&c| c < cells) {
+                let (x, width) = extents[c_index];
+                let (x, y, width, height) = table.inset_box(x, row.top - row.height, width, row.height);
+                layer.add_shape(self::rect_shape(table, x, y, width, height, true, false));
             }
-            let (x, y) = self::calculate_cell_coordinates(&page_size, &table, c_index, r_index + cmp::min(page_num, 1) - current_row);
-            new_layer_ref.use_text(cell,  12.0, Mm(x), Mm(y), regular);
-            current_y = y;
+            layer.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+        }
+        let struck = table.struck_rows.contains(&row.index);
+        if let (true, Some(color)) = (struck, &table.struck_text_color) {
+            layer.set_fill_color(color.clone());
+        }
+        for (c_index, &extent) in extents.iter().enumerate().take(cells) {
+            self::draw_cell(layer, table, c_index, extent, row, regular);
         }
+        if struck {
+            layer.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+            let (x, width) = table.extent(page_size);
+            let middle = row.top - row.height / 2.0;
+            layer.add_shape(self::line_shape(table, x, middle, x + width, middle));
+        }
+        self::draw_emphasis_rules(layer, table, row, &extents, cells);
+        return;
+    }
+
+    let style = summary_style.unwrap_or(header_style);
+    let font = match style.bold {
+        true => bold,
+        false => regular
+    };
+    if let Some(fill) = &style.fill {
+        layer.set_fill_color(fill.clone());
+        for &(x, width) in extents.iter().take(cells) {
+            let (x, y, width, height) = table.inset_box(x, row.top - row.height, width, row.height);
+            layer.add_shape(self::rect_shape(table, x, y, width, height, true, false));
+        }
+    }
+    layer.set_fill_color(style.text_color.clone().unwrap_or_else(|| Color::Greyscale(Greyscale::new(0.0, None))));
+    for (c_index, &extent) in extents.iter().enumerate().take(cells) {
+        self::draw_cell(layer, table, c_index, extent, row, font);
+    }
+    layer.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+    self::draw_emphasis_rules(layer, table, row, &extents, cells);
+
+    let (x, width) = table.extent(page_size);
+    match (row.index, &table.summary_row) {
+        (0, _) => if let Some(rule) = &table.header_rule {
+            let bottom = row.top - row.height;
+            self::draw_rule(layer, rule, self::line_shape(table, x, bottom, x + width, bottom));
+        },
+        (_, Some(SummaryRow { double_rule: Some(rule), .. })) => {
+            for y in [row.top, row.top - DOUBLE_RULE_GAP] {
+                self::draw_rule(layer, rule, self::line_shape(table, x, y, x + width, y));
+            }
+        }
+        _ => ()
     }
-    (current_y, new_layer_ref)
 }
 
-pub fn generate_barcode(content: String, height: u32) -> Image {
-    let barcode = Code128::new(content).unwrap();
-    let buffer = barcoders::generators::image::Image::image_buffer(height);
-    let encoded = barcode.encode();
-    let buffer = buffer.generate_buffer(&encoded[..]).unwrap();
+fn draw_emphasis_rules(layer: &PdfLayerReference, table: &Table, row: &PlacedRow, extents: &[(f64, f64)], cells: usize) {
+    let emphasis = match &table.column_emphasis {
+        Some(emphasis) => emphasis,
+        None => return
+    };
+    if let Some(rule) = &emphasis.left_rule {
+        for &c_index in emphasis.columns.iter().filter(|&&c| c < cells) {
+            let (x, _) = extents[c_index];
+            self::draw_rule(layer, rule, self::line_shape(table, x, row.top, x, row.top - row.height));
+        }
+    }
+}
+
+fn line_shape(table: &Table, x1: f64, y1: f64, x2: f64, y2: f64) -> Line {
+    let snap = |value: f64| self::snap_to_grid(value, table.snap_to);
+    Line {
+        points: vec![(Point::new(Mm(snap(x1)), Mm(snap(y1))), false), (Point::new(Mm(snap(x2)), Mm(snap(y2))), false)],
+        is_closed: false,
+        has_fill: false,
+        has_stroke: true,
+        is_clipping_path: false,
+    }
+}
+
+fn draw_rule(layer: &PdfLayerReference, rule: &Rule, line: Line) {
+    layer.set_outline_color(rule.color.clone());
+    layer.set_outline_thickness(rule.thickness);
+    layer.add_shape(line);
+    layer.set_outline_color(Color::Greyscale(Greyscale::new(0.0, None)));
+    layer.set_outline_thickness(1.0);
+}
+
+// Splits the table into its column groups, each with its own layout. The first group starts at y,
+// the others at the top of a new page.
+fn measure_column_groups(table: &Table, page_size: &PageSize, y: f64) -> Vec<(Table, TableLayout)> {
+    let groups = table.column_groups();
+    if groups.len() == 1 {
+        return vec![(table.clone(), self::measure_table(table, page_size, y))];
+    }
+    groups.iter().enumerate().map(|(g_index, group)| {
+        let mut part = table.select_columns(group);
+        if g_index > 0 {
+            part.position_y = page_size.printable_top();
+            part.caption = None;
+        }
+        let start_y = match g_index {
+            0 => y,
+            _ => part.position_y
+        };
+        let layout = self::measure_table(&part, page_size, start_y);
+        (part, layout)
+    }).collect()
+}
+
+// Number of pages add_table will draw the table on, counting every column group
+pub fn table_page_count(table: &Table, page_size: &PageSize, y: f64) -> usize {
+    self::measure_column_groups(table, page_size, y).iter().map(|(_, layout)| layout.pages.len()).sum()
+}
+
+// Decides where a table goes when it continues onto another page. page_num is the number of pages
+// of the table drawn so far. Rows are broken using the page size passed to add_table, the returned
+// size only changes the widths the next page is drawn with, so the pages should share a height.
+pub trait PageProvider {
+    fn next_page(&mut self, page_num: usize) -> (PdfLayerReference, PageSize);
+}
+
+// Adds a page of the same size to the document for every break, named after its number
+pub struct NewPages<'a> {
+    pub doc: &'a PdfDocumentReference,
+    pub page_size: PageSize
+}
+
+impl<'a> PageProvider for NewPages<'a> {
+    fn next_page(&mut self, page_num: usize) -> (PdfLayerReference, PageSize) {
+        let (new_page, new_layer) = self.doc.add_page(Mm(self.page_size.width), Mm(self.page_size.height), page_num.to_string());
+        (self.doc.get_page(new_page).get_layer(new_layer), self.page_size)
+    }
+}
+
+// Moves through layers created beforehand, the first being the one the table starts on. Panics past
+// the last layer, add_table_to_layers checks there are enough before drawing anything.
+pub struct ExistingLayers<'a> {
+    pub layers: &'a [PdfLayerReference],
+    pub page_size: PageSize
+}
+
+impl<'a> PageProvider for ExistingLayers<'a> {
+    fn next_page(&mut self, page_num: usize) -> (PdfLayerReference, PageSize) {
+        match self.layers.get(page_num) {
+            Some(layer) => (layer.clone(), self.page_size),
+            None => panic!("Table needs more than the {} pages provided", self.layers.len())
+        }
+    }
+}
+
+pub fn add_table(table: &mut Table, page_size: &PageSize, doc: &PdfDocumentReference, current_layer_ref: PdfLayerReference, y: f64, regular: &IndirectFontRef, bold: &IndirectFontRef) -> (f64, PdfLayerReference) {
+    let mut pages = NewPages { doc, page_size: *page_size };
+    self::add_table_with_pages(table, page_size, doc, current_layer_ref, y, regular, bold, &mut pages)
+}
+
+// Like add_table but draws onto pages created beforehand, starting on the first layer and moving to
+// the next one each time the table breaks. Fails before drawing if the table needs more pages than
+// there are layers.
+pub fn add_table_to_layers(table: &mut Table, page_size: &PageSize, doc: &PdfDocumentReference, layers: &[PdfLayerReference], y: f64, regular: &IndirectFontRef, bold: &IndirectFontRef) -> Result<(f64, PdfLayerReference), TableError> {
+    // the fonts are needed to measure wrapped rows
+    table.set_fonts(regular, bold);
+    let pages = self::table_page_count(table, page_size, y);
+    let first = match layers.first() {
+        Some(layer) if pages <= layers.len() => layer.clone(),
+        _ => return Err(TableError::NotEnoughLayers { pages, layers: layers.len() })
+    };
+    let mut pages = ExistingLayers { layers, page_size: *page_size };
+    Ok(self::add_table_with_pages(table, page_size, doc, first, y, regular, bold, &mut pages))
+}
+
+// Like add_table with the pages after the first coming from the provider
+#[allow(clippy::too_many_arguments)]
+pub fn add_table_with_pages(table: &mut Table, page_size: &PageSize, doc: &PdfDocumentReference, current_layer_ref: PdfLayerReference, y: f64, regular: &IndirectFontRef, bold: &IndirectFontRef, pages: &mut dyn PageProvider) -> (f64, PdfLayerReference) {
+    let _cache = MeasureCache::scope();
+    table.set_fonts(regular, bold);
+    let parts = self::measure_column_groups(table, page_size, y);
+    // a table starting below the bottom margin leaves its first page empty, the bookmark and page
+    // callback skip that page
+    let total_pages: usize = parts.iter().map(|(_, layout)| layout.pages.iter().filter(|page| !page.rows.is_empty()).count()).sum();
+    let mut bookmark = table.title.clone();
+    // with a layer name the table goes on a layer of its own on each of its pages
+    let layer_name = table.layer_name.clone();
+    let own_layer = |layer: PdfLayerReference| match &layer_name {
+        Some(name) => doc.get_page(layer.page).add_layer(name.as_str()),
+        None => layer
+    };
+    let mut new_layer_ref = own_layer(current_layer_ref);
+    let mut draw_size = *page_size;
+    let mut page_count = 0;
+    let mut pages_with_rows = 0;
+    let mut end_y = y;
+
+    for (mut part, layout) in parts {
+        if let Some(caption) = &part.caption {
+            // half a row above the header whatever position_y refers to, which is position_y itself
+            // with the default anchor
+            let caption_y = part.header_top(page_size, part.first_row_top(part.position_y)) + part.scaled_row_height() * 0.5;
+            new_layer_ref.use_text(caption.as_str(), part.font_size(), Mm(part.extent(&draw_size).0), Mm(caption_y), bold);
+        }
+
+        for page in layout.pages.iter() {
+            if page_count > 0 {
+                let (layer, size) = pages.next_page(page_count);
+                new_layer_ref = own_layer(layer);
+                draw_size = size;
+            }
+            part.position_y = page.position_y;
+            let header_style = match page_count {
+                0 => &part.header_style,
+                _ => part.continuation_header_style.as_ref().unwrap_or(&part.header_style)
+            };
+            for row in page.rows.iter() {
+                self::draw_row(&new_layer_ref, &draw_size, &part, row, header_style, regular, bold);
+            }
+            self::draw_merged_cells(&new_layer_ref, &draw_size, &part, &page.rows, regular);
+            self::draw_outer_border(&new_layer_ref, &draw_size, &part, &page.rows);
+            page_count += 1;
+            if page.rows.is_empty() {
+                continue;
+            }
+            if let Some(title) = bookmark.take() {
+                doc.add_bookmark(title.as_str(), new_layer_ref.page);
+            }
+            pages_with_rows += 1;
+            if let Some(callback) = &part.page_callback {
+                callback(&new_layer_ref, pages_with_rows, total_pages);
+            }
+        }
+        table.position_y = part.position_y;
+        end_y = layout.end_y;
+    }
+    (end_y, new_layer_ref)
+}
+
+// Returns the natural (width, height) of an image in mm when placed at the given dpi
+pub fn image_size_mm(image: &printpdf::Image, dpi: f64) -> (f64, f64) {
+    let width: Mm = image.image.width.into_pt(dpi).into();
+    let height: Mm = image.image.height.into_pt(dpi).into();
+    (width.0, height.0)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BarcodeError {
+    InvalidCharacter,
+    InvalidLength,
+    Generate
+}
+
+impl fmt::Display for BarcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BarcodeError::InvalidCharacter => write!(f, "Barcode content contains a character the symbology can't encode"),
+            BarcodeError::InvalidLength => write!(f, "Barcode content has an invalid length for the symbology"),
+            BarcodeError::Generate => write!(f, "Barcode image could not be generated")
+        }
+    }
+}
+
+impl std::error::Error for BarcodeError {}
+
+impl From<barcoders::error::Error> for BarcodeError {
+    fn from(error: barcoders::error::Error) -> Self {
+        match error {
+            barcoders::error::Error::Character => BarcodeError::InvalidCharacter,
+            barcoders::error::Error::Length => BarcodeError::InvalidLength,
+            barcoders::error::Error::Generate => BarcodeError::Generate
+        }
+    }
+}
+
+// Turns content into the bar modules (1 = bar, 0 = space) of a symbology. Implement it to
+// render a symbology that isn't built in.
+pub trait BarcodeEncoder {
+    fn encode(&self, content: &str) -> Result<Vec<u8>, BarcodeError>;
+}
+
+// Content must start with a character-set selector, e.g. "ƁABC123"
+pub struct Code128Encoder;
+
+impl BarcodeEncoder for Code128Encoder {
+    fn encode(&self, content: &str) -> Result<Vec<u8>, BarcodeError> {
+        Ok(Code128::new(content)?.encode())
+    }
+}
+
+pub struct Code39Encoder;
+
+impl BarcodeEncoder for Code39Encoder {
+    fn encode(&self, content: &str) -> Result<Vec<u8>, BarcodeError> {
+        Ok(Code39::new(content)?.encode())
+    }
+}
+
+// 12 digits, the check digit is added by the encoder
+pub struct Ean13Encoder;
+
+impl BarcodeEncoder for Ean13Encoder {
+    fn encode(&self, content: &str) -> Result<Vec<u8>, BarcodeError> {
+        Ok(EAN13::new(content)?.encode())
+    }
+}
+
+// Renders content with any encoder into an image height pixels tall, one pixel per module
+pub fn encode_barcode(encoder: &dyn BarcodeEncoder, content: &str, height: u32) -> Result<Image, BarcodeError> {
+    self::encode_barcode_with_min_bar(encoder, content, height, 1)
+}
+
+// Like encode_barcode but widens every module so the narrowest bar is at least min_bar_px pixels,
+// for printers that lose thin bars. Bars and spaces keep their proportions.
+pub fn encode_barcode_with_min_bar(encoder: &dyn BarcodeEncoder, content: &str, height: u32, min_bar_px: u32) -> Result<Image, BarcodeError> {
+    self::encode_barcode_with_threshold(encoder, content, height, min_bar_px, None)
+}
+
+// Like encode_barcode_with_min_bar but with a threshold every pixel becomes pure black or white:
+// black when its luminance is below the threshold, so Some(255) turns anything not white into a bar
+pub fn encode_barcode_with_threshold(encoder: &dyn BarcodeEncoder, content: &str, height: u32, min_bar_px: u32, threshold: Option<u8>) -> Result<Image, BarcodeError> {
+    let encoded = encoder.encode(content)?;
+    let narrowest = encoded.split(|&module| module == 0).filter(|bar| !bar.is_empty()).map(|bar| bar.len() as u32).min().unwrap_or(1);
+    let buffer = barcoders::generators::image::Image::ImageBuffer {
+        height,
+        xdim: min_bar_px.max(1).div_ceil(narrowest),
+        rotation: barcoders::generators::image::Rotation::Zero,
+        foreground: barcoders::generators::image::Color::black(),
+        background: barcoders::generators::image::Color::white()
+    };
+    let buffer = buffer.generate_buffer(&encoded[..])?;
     let mut img = Image::new(buffer.width(), height);
 
     for (x, y, &color) in buffer.enumerate_pixels() {
-        img.set_pixel(x, y, Pixel::new(color[0], color[1], color[2]));
+        let pixel = match threshold {
+            Some(threshold) => {
+                let luminance = 0.299 * color[0] as f64 + 0.587 * color[1] as f64 + 0.114 * color[2] as f64;
+                match luminance < threshold as f64 {
+                    true => Pixel::new(0, 0, 0),
+                    false => Pixel::new(255, 255, 255)
+                }
+            }
+            None => Pixel::new(color[0], color[1], color[2])
+        };
+        img.set_pixel(x, y, pixel);
     }
-    return img;
+    Ok(img)
+}
+
+pub fn generate_barcode(content: String, height: u32) -> Image {
+    self::encode_barcode(&Code128Encoder, &content, height).unwrap()
 }
 
 pub fn generate_barcode_for_pdf(content: String, height: u32) -> printpdf::Image {
-    let img = self::generate_barcode(content, height as u32);
+    self::bmp_to_pdf_image(&self::generate_barcode(content, height))
+}
+
+// Draws a barcode as rectangles in the current fill colour, one per bar, with its bottom-left
+// corner at (x, y). The bars stay sharp at any zoom and take a fraction of the space of an image.
+// Modules are as wide as the pixels of generate_barcode_for_pdf, so both take the same footprint.
+// Returns the width of the barcode in mm.
+// Use this for a barcode repeated on every page, e.g. a document ID. printpdf keeps images in the
+// resources of the page they are drawn on and can't reference one from other pages, so every image
+// stamp stores the pixels again, while the bars here are a few path operators per page.
+pub fn generate_barcode_vector_for_pdf(layer: &PdfLayerReference, encoder: &dyn BarcodeEncoder, content: &str, height_mm: f64, x: f64, y: f64) -> Result<f64, BarcodeError> {
+    let encoded = encoder.encode(content)?;
+    let module = 25.4 / IMAGE_DPI;
+    let mut start = None;
+    for (index, &bit) in encoded.iter().chain(std::iter::once(&0)).enumerate() {
+        match (bit, start) {
+            (1, None) => start = Some(index),
+            (0, Some(first)) => {
+                let left = x + first as f64 * module;
+                let right = x + index as f64 * module;
+                layer.add_shape(Line {
+                    points: vec![
+                        (Point::new(Mm(left), Mm(y + height_mm)), false),
+                        (Point::new(Mm(right), Mm(y + height_mm)), false),
+                        (Point::new(Mm(right), Mm(y)), false),
+                        (Point::new(Mm(left), Mm(y)), false),
+                    ],
+                    is_closed: true,
+                    has_fill: true,
+                    has_stroke: false,
+                    is_clipping_path: false,
+                });
+                start = None;
+            }
+            _ => ()
+        }
+    }
+    Ok(encoded.len() as f64 * module)
+}
+
+fn bmp_to_pdf_image(img: &Image) -> printpdf::Image {
     let mut tr: Vec<u8> = vec![];
     img.to_writer(&mut tr).unwrap();
     let file = Cursor::new(tr);
@@ -253,4 +2256,31 @@ pub fn generate_barcode_for_pdf(content: String, height: u32) -> printpdf::Image
             panic!("Can't open image");
         }
     }
-}
\ No newline at end of file
+}
+
+pub struct BarcodeCaption {
+    pub text: String,
+    pub align: Alignment,
+    pub font: IndirectFontRef,
+    pub font_size: f64
+}
+
+// Draws a barcode with its bottom-left corner at (x, y) and an optional caption beneath it, aligned
+// to the barcode's footprint. Nothing is drawn when the content can't be encoded.
+pub fn add_barcode(layer: &PdfLayerReference, encoder: &dyn BarcodeEncoder, content: &str, height: u32, x: f64, y: f64, caption: Option<&BarcodeCaption>) -> Result<(), BarcodeError> {
+    let barcode = self::bmp_to_pdf_image(&self::encode_barcode(encoder, content, height)?);
+    let (width, _) = self::image_size_mm(&barcode, IMAGE_DPI);
+    barcode.add_to_layer(layer.clone(), Some(Mm(x)), Some(Mm(y)), None, None, None, Some(IMAGE_DPI));
+
+    if let Some(caption) = caption {
+        let anchor = match caption.align {
+            Alignment::Left => x,
+            Alignment::Center => x + width / 2.0,
+            Alignment::Right => x + width
+        };
+        let caption_x = aligned_x(&caption.text, anchor, caption.align, &caption.font, caption.font_size);
+        let caption_y = y - caption.font_size * PT_TO_MM;
+        layer.use_text(caption.text.as_str(), caption.font_size, Mm(caption_x), Mm(caption_y), &caption.font);
+    }
+    Ok(())
+}
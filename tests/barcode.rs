@@ -0,0 +1,31 @@
+use printpdf_utils::utils::*;
+
+// Lengths of the runs of dark pixels along the first row of the image
+fn bar_widths(image: &bmp::Image) -> Vec<u32> {
+    let mut widths = Vec::new();
+    let mut run = 0;
+    for x in 0..image.get_width() {
+        match image.get_pixel(x, 0).r < 128 {
+            true => run += 1,
+            false if run > 0 => {
+                widths.push(run);
+                run = 0;
+            }
+            false => {}
+        }
+    }
+    if run > 0 {
+        widths.push(run);
+    }
+    widths
+}
+
+#[test]
+fn min_bar_px_widens_the_narrowest_bar() {
+    let plain = encode_barcode(&Code128Encoder, "ƁABC123", 10).unwrap();
+    assert_eq!(bar_widths(&plain).into_iter().min(), Some(1));
+
+    let widened = encode_barcode_with_min_bar(&Code128Encoder, "ƁABC123", 10, 3).unwrap();
+    assert!(bar_widths(&widened).into_iter().all(|width| width >= 3));
+    assert_eq!(widened.get_width(), plain.get_width() * 3);
+}
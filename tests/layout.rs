@@ -0,0 +1,213 @@
+use printpdf::{Mm, Point};
+use printpdf_utils::utils::*;
+
+fn assert_close(actual: (f64, f64), expected: (f64, f64)) {
+    assert!((actual.0 - expected.0).abs() < 1e-9, "x: {} != {}", actual.0, expected.0);
+    assert!((actual.1 - expected.1).abs() < 1e-9, "y: {} != {}", actual.1, expected.1);
+}
+
+fn sample_table(position: f64, borders: bool) -> Table {
+    let mut table = Table::default(position);
+    table.set_borders(borders);
+    for r in 0..4 {
+        table.add_row((0..4).map(|c| format!("{}:{}", r, c)).collect());
+    }
+    table
+}
+
+#[test]
+fn column_coordinates_a4() {
+    let page = PageSize::A4();
+    assert_close(calculate_column_coordinates(&page, 0, 4, 100.0), (10.0, 100.0));
+    assert_close(calculate_column_coordinates(&page, 2, 4, 100.0), (105.0, 100.0));
+}
+
+#[test]
+fn column_coordinates_a5() {
+    let page = PageSize::A5();
+    assert_close(calculate_column_coordinates(&page, 3, 4, 50.0), (106.0, 50.0));
+}
+
+#[test]
+fn column_coordinates_clamp_to_top_margin() {
+    assert_close(calculate_column_coordinates(&PageSize::A4(), 0, 4, 500.0), (10.0, 287.0));
+    assert_close(calculate_column_coordinates(&PageSize::A5(), 0, 4, 500.0), (10.0, 200.0));
+}
+
+#[test]
+#[should_panic]
+fn column_coordinates_index_out_of_range() {
+    calculate_column_coordinates(&PageSize::A4(), 4, 4, 100.0);
+}
+
+#[test]
+fn cell_coordinates_without_borders() {
+    let page = PageSize::A4();
+    let table = sample_table(200.0, false);
+    assert_close(calculate_cell_coordinates(&page, &table, 0, 0), (10.0, 192.5));
+    assert_close(calculate_cell_coordinates(&page, &table, 1, 2), (105.0, 177.5));
+    assert_close(calculate_cell_coordinates(&page, &table, 3, 3), (10.0 + 190.0 * 10.0 / 12.0, 170.0));
+}
+
+#[test]
+fn cell_coordinates_with_borders() {
+    let page = PageSize::A4();
+    let table = sample_table(200.0, true);
+    assert_close(calculate_cell_coordinates(&page, &table, 0, 0), (11.875, 191.5));
+    assert_close(calculate_cell_coordinates(&page, &table, 1, 2), (106.875, 176.5));
+}
+
+#[test]
+fn cell_coordinates_a5() {
+    let page = PageSize::A5();
+    let table = sample_table(150.0, false);
+    assert_close(calculate_cell_coordinates(&page, &table, 1, 1), (10.0 + 128.0 / 2.0, 135.0));
+}
+
+#[test]
+#[should_panic]
+fn cell_coordinates_row_out_of_range() {
+    calculate_cell_coordinates(&PageSize::A4(), &sample_table(200.0, false), 0, 4);
+}
+
+#[test]
+fn border_points_a4() {
+    let page = PageSize::A4();
+    let table = sample_table(200.0, true);
+    let right = 10.0 + 190.0 * 8.0 / 12.0;
+    let expected = vec![
+        Point::new(Mm(105.0), Mm(188.75)),
+        Point::new(Mm(right), Mm(188.75)),
+        Point::new(Mm(right), Mm(181.25)),
+        Point::new(Mm(105.0), Mm(181.25)),
+    ];
+    let points: Vec<Point> = calculate_border_points(&page, &table, 1, 1).into_iter().map(|(p, _)| p).collect();
+    assert_eq!(points, expected);
+}
+
+#[test]
+fn border_points_a5_first_cell() {
+    let page = PageSize::A5();
+    let table = sample_table(150.0, true);
+    let right = 10.0 + 128.0 * 6.0 / 12.0;
+    let expected = vec![
+        Point::new(Mm(10.0), Mm(146.25)),
+        Point::new(Mm(right), Mm(146.25)),
+        Point::new(Mm(right), Mm(138.75)),
+        Point::new(Mm(10.0), Mm(138.75)),
+    ];
+    let points: Vec<Point> = calculate_border_points(&page, &table, 0, 0).into_iter().map(|(p, _)| p).collect();
+    assert_eq!(points, expected);
+}
+
+#[test]
+fn cell_coordinates_at_half_scale() {
+    let page = PageSize::A4();
+    let full = sample_table(200.0, true);
+    let mut half = sample_table(200.0, true);
+    half.set_scale(0.5);
+
+    let (full_x, full_y) = calculate_cell_coordinates(&page, &full, 2, 3);
+    let (half_x, half_y) = calculate_cell_coordinates(&page, &half, 2, 3);
+    assert_close((half_x - 10.0, 200.0 - half_y), ((full_x - 10.0) * 0.5, (200.0 - full_y) * 0.5));
+
+    let (_, _, full_width, full_height) = calculate_cell_bounds(&page, &full, 1, 1);
+    let (_, _, half_width, half_height) = calculate_cell_bounds(&page, &half, 1, 1);
+    assert_close((half_width, half_height), (full_width * 0.5, full_height * 0.5));
+}
+
+#[test]
+fn first_row_position_for_each_anchor() {
+    let page = PageSize::A4();
+    let mut table = sample_table(200.0, true);
+
+    let (_, bottom, _, height) = calculate_cell_bounds(&page, &table, 0, 0);
+    assert_close((bottom + height, calculate_cell_coordinates(&page, &table, 0, 0).1), (196.25, 191.5));
+
+    table.set_position_anchor(PositionAnchor::HeaderTop);
+    let (_, bottom, _, height) = calculate_cell_bounds(&page, &table, 0, 0);
+    assert_close((bottom + height, calculate_cell_coordinates(&page, &table, 0, 0).1), (200.0, 195.25));
+
+    table.set_position_anchor(PositionAnchor::FirstBaseline);
+    let (_, bottom, _, height) = calculate_cell_bounds(&page, &table, 0, 0);
+    assert_close((bottom + height, calculate_cell_coordinates(&page, &table, 0, 0).1), (204.75, 200.0));
+}
+
+#[test]
+fn right_aligned_table_ends_at_the_right_margin() {
+    let page = PageSize::A4();
+    let mut table = sample_table(200.0, true);
+    table.set_columns_len(20);
+    table.set_horizontal_align(printpdf_utils::text::Alignment::Right);
+
+    let (x, _, width, _) = calculate_cell_bounds(&page, &table, 3, 0);
+    assert!((x + width - (page.width - page.margin_width)).abs() < 1e-9, "right edge: {}", x + width);
+    assert!(calculate_cell_bounds(&page, &table, 0, 0).0 > page.margin_width);
+}
+
+#[test]
+fn positioned_rows_report_their_baselines() {
+    let page = PageSize::A4();
+    let mut table = sample_table(200.0, true);
+    table.set_row_y_positions(vec![250.0, 100.0]);
+
+    assert_close(calculate_cell_coordinates(&page, &table, 0, 0), (11.875, 250.0));
+    assert_close(calculate_cell_coordinates(&page, &table, 1, 1), (106.875, 100.0));
+    let (_, bottom, _, height) = calculate_cell_bounds(&page, &table, 0, 1);
+    // row 2 has no position of its own and follows on below row 1
+    let (_, next_bottom, _, next_height) = calculate_cell_bounds(&page, &table, 0, 2);
+    assert!((next_bottom + next_height - bottom).abs() < 1e-9, "row 2 top: {}", next_bottom + next_height);
+    assert!(bottom < 100.0 && bottom + height > 100.0, "row 1 spans {} to {}", bottom, bottom + height);
+}
+
+#[test]
+fn tall_rows_move_to_the_next_page() {
+    let page = PageSize::A4();
+    let mut table = Table::default(280.0);
+    for r in 0..40 {
+        table.add_row((0..4).map(|c| format!("{}:{}", r, c)).collect());
+    }
+    let mut heights = vec![None; 40];
+    heights[34] = Some(60.0);
+    table.set_row_heights(heights);
+
+    let layout = measure_table(&table, &page, 280.0);
+    for row in layout.pages.iter().flat_map(|page| page.rows.iter()) {
+        assert!(row.top - row.height >= page.margin_height, "row {} ends at {}", row.index, row.top - row.height);
+    }
+    assert!(layout.pages[1].rows.iter().any(|row| row.index == 34));
+}
+
+#[test]
+fn horizontal_pagination_splits_at_columns_len() {
+    let mut table = Table::default(200.0);
+    table.set_horizontal_paginate(true);
+    table.set_columns((0..20).map(|_| Column::default()).collect());
+    assert_eq!(table.column_groups().len(), 1);
+
+    table.set_columns_len(12);
+    table.set_repeat_column(Some(0));
+    let groups = table.column_groups();
+    assert_eq!(groups.len(), 2);
+    assert!(groups.iter().all(|group| group[0] == 0 && group.len() <= 12));
+    assert_eq!(groups.iter().map(|group| group.len() - 1).sum::<usize>(), 19);
+}
+
+#[test]
+fn wrapped_rows_stay_on_the_page() {
+    let page = PageSize::A4();
+    let mut table = Table::default(280.0);
+    table.set_columns(vec![Column { width: 1, max_lines: Some(20), ..Column::default() }, Column { width: 3, max_lines: Some(500), ..Column::default() }]);
+    table.add_row(vec!["Notes", "Details"]);
+    for r in 0..6 {
+        table.add_row(vec![format!("note {} ", r).repeat(40), "x".into()]);
+    }
+    // far more lines than a page holds, cut to the page
+    table.add_row(vec!["last".to_string(), "lorem ipsum dolor sit amet ".repeat(200)]);
+
+    let layout = measure_table(&table, &page, 280.0);
+    for row in layout.pages.iter().flat_map(|page| page.rows.iter()) {
+        assert!(row.top - row.height >= page.margin_height - 1e-9, "row {} ends at {}", row.index, row.top - row.height);
+    }
+    assert!(layout.pages.last().unwrap().rows.iter().any(|row| row.index == 7));
+}